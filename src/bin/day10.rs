@@ -12,53 +12,556 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use aoc2022::Args;
+
 fn main() {
-    let input: Vec<Option<i64>> = std::io::stdin()
-        .lines()
-        .map(|l| {
-            let l = l.as_ref().unwrap();
-            if l == "noop" {
-                return None;
+    let lines: Vec<String> = std::io::stdin().lines().map(Result::unwrap).collect();
+    let program: Vec<Instruction> = lines.iter().map(|l| Instruction::parse(l)).collect();
+    let config = Config::from_args(std::env::args().skip(1));
+
+    let mut signal = SignalStrength::default();
+    let mut crt = Crt::new(config.width, config.height, config.sprite_width);
+    let mut cpu = Cpu::new(&program);
+    if config.trace || !config.breakpoints.is_empty() {
+        debug(&mut cpu, &config, &mut signal, &mut crt);
+    } else {
+        // The CRT has no more pixels to draw once every pixel has had its
+        // cycle, but the signal strength is still sampled until the program
+        // ends.
+        cpu.run_until(crt.pixels.len() as u64, &mut [&mut signal, &mut crt]);
+        cpu.run(&mut [&mut signal]);
+    }
+
+    println!("{}", signal.sum);
+
+    match config.screen {
+        ScreenFormat::Ascii => print!("{}", render_ascii(&crt)),
+        ScreenFormat::Blocks => print!("{}", render_blocks(&crt)),
+    }
+
+    if let Some(path) = &config.pbm {
+        let file = std::fs::File::create(path).unwrap();
+        write_pbm(&crt, std::io::BufWriter::new(file)).unwrap();
+    }
+
+    if crt.height == GLYPH_HEIGHT {
+        match read_letters(&crt.pixels, crt.width) {
+            Ok(text) => println!("{text}"),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+}
+
+// Command line options:
+//
+//   --width N               CRT width (default 40)
+//   --height N              CRT height (default 6)
+//   --sprite N              sprite width (default 3)
+//   --screen ascii|blocks   how to draw the screen
+//   --pbm PATH              also write the screen as a PBM image
+//   --trace                 print every cycle
+//   --break-cycle N         stop after cycle N
+//   --break-x N             stop once X changes to N
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Config {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    screen: ScreenFormat,
+    pbm: Option<std::path::PathBuf>,
+    trace: bool,
+    breakpoints: Breakpoints,
+}
+
+impl Config {
+    fn from_args(args: impl Iterator<Item = String>) -> Config {
+        let mut config = Config {
+            width: 40,
+            height: 6,
+            sprite_width: 3,
+            screen: ScreenFormat::Ascii,
+            pbm: None,
+            trace: false,
+            breakpoints: Breakpoints::default(),
+        };
+
+        let mut args = Args::new(args);
+        let positive = |args: &mut Args<_>| match args.value::<i64>() {
+            n if n > 0 => n as usize,
+            n => panic!("{} must be positive, not {n}", args.flag()),
+        };
+        while let Some(flag) = args.next_flag() {
+            match flag {
+                "--width" => config.width = positive(&mut args),
+                "--height" => config.height = positive(&mut args),
+                "--sprite" => config.sprite_width = positive(&mut args),
+                "--screen" => {
+                    config.screen = match args.string().as_str() {
+                        "ascii" => ScreenFormat::Ascii,
+                        "blocks" => ScreenFormat::Blocks,
+                        s => panic!("unknown screen format {s:?}"),
+                    }
+                }
+                "--pbm" => config.pbm = Some(args.string().into()),
+                "--trace" => config.trace = true,
+                "--break-cycle" => config.breakpoints.cycles.push(positive(&mut args) as u64),
+                "--break-x" => config.breakpoints.x_values.push(args.value()),
+                _ => args.unknown(),
+            }
+        }
+
+        config
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ScreenFormat {
+    Ascii,
+    Blocks,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Instruction {
+    Noop,
+    Addx(i64),
+}
+
+impl Instruction {
+    fn parse(line: &str) -> Instruction {
+        let mut iter = line.split(' ');
+        match iter.next() {
+            Some("noop") => Instruction::Noop,
+            Some("addx") => Instruction::Addx(iter.next().unwrap().parse().unwrap()),
+            _ => panic!("unknown instruction {line:?}"),
+        }
+    }
+
+    fn cycles(self) -> u64 {
+        match self {
+            Instruction::Noop => 1,
+            Instruction::Addx(_) => 2,
+        }
+    }
+
+    // Takes effect at the end of the instruction's last cycle.
+    fn execute(self, x: &mut i64) {
+        match self {
+            Instruction::Noop => (),
+            Instruction::Addx(n) => *x += n,
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Instruction::Noop => write!(f, "noop"),
+            Instruction::Addx(n) => write!(f, "addx {n}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Tick {
+    // Numbered from 1, as in the puzzle.
+    cycle: u64,
+    // X during the cycle.
+    x: i64,
+    pc: usize,
+    instruction: Instruction,
+}
+
+trait CycleHook {
+    fn during(&mut self, tick: &Tick);
+}
+
+impl<F: FnMut(&Tick)> CycleHook for F {
+    fn during(&mut self, tick: &Tick) {
+        self(tick)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Cpu<'a> {
+    program: &'a [Instruction],
+    pc: usize,
+    x: i64,
+    // Cycles completed so far.
+    cycle: u64,
+    // Cycles spent on the current instruction.
+    progress: u64,
+}
+
+impl<'a> Cpu<'a> {
+    fn new(program: &'a [Instruction]) -> Cpu<'a> {
+        Cpu {
+            program,
+            pc: 0,
+            x: 1,
+            cycle: 0,
+            progress: 0,
+        }
+    }
+
+    fn halted(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+
+    fn step(&mut self, hooks: &mut [&mut dyn CycleHook]) -> Option<Tick> {
+        let instruction = *self.program.get(self.pc)?;
+        self.cycle += 1;
+        let tick = Tick {
+            cycle: self.cycle,
+            x: self.x,
+            pc: self.pc,
+            instruction,
+        };
+        for hook in hooks.iter_mut() {
+            hook.during(&tick);
+        }
+
+        self.progress += 1;
+        if self.progress == instruction.cycles() {
+            instruction.execute(&mut self.x);
+            self.pc += 1;
+            self.progress = 0;
+        }
+
+        Some(tick)
+    }
+
+    fn run_until(&mut self, cycle: u64, hooks: &mut [&mut dyn CycleHook]) {
+        while self.cycle < cycle && self.step(hooks).is_some() {}
+    }
+
+    fn run(&mut self, hooks: &mut [&mut dyn CycleHook]) {
+        while !self.halted() {
+            self.step(hooks);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Breakpoints {
+    cycles: Vec<u64>,
+    x_values: Vec<i64>,
+}
+
+impl Breakpoints {
+    fn is_empty(&self) -> bool {
+        self.cycles.is_empty() && self.x_values.is_empty()
+    }
+
+    // `x_after` is X at the end of the cycle.
+    fn check(&self, tick: &Tick, x_after: i64) -> Option<String> {
+        if self.cycles.contains(&tick.cycle) {
+            return Some(format!("breakpoint at cycle {}", tick.cycle));
+        }
+        if x_after != tick.x && self.x_values.contains(&x_after) {
+            return Some(format!("breakpoint on X = {x_after}"));
+        }
+        None
+    }
+}
+
+fn debug(cpu: &mut Cpu, config: &Config, signal: &mut SignalStrength, crt: &mut Crt) {
+    while let Some(tick) = cpu.step(&mut [signal, crt]) {
+        if config.trace {
+            println!("{}", trace_line(&tick, cpu.x, crt));
+        }
+        if let Some(hit) = config.breakpoints.check(&tick, cpu.x) {
+            let next = match cpu.program.get(cpu.pc) {
+                Some(instruction) => format!("{instruction} at {}", cpu.pc),
+                None => "end of program".to_string(),
+            };
+            println!("{hit}: X = {}, next {next}", cpu.x);
+            return;
+        }
+    }
+}
+
+fn trace_line(tick: &Tick, x_after: i64, crt: &Crt) -> String {
+    let pos = (tick.cycle - 1) as usize;
+    let pixel = match crt.pixels.get(pos) {
+        Some(lit) => format!(
+            "{} at ({}, {})",
+            if *lit { '#' } else { '.' },
+            pos % crt.width,
+            pos / crt.width
+        ),
+        None => "off screen".to_string(),
+    };
+    let instruction = format!("{:>4}: {}", tick.pc, tick.instruction);
+    format!(
+        "cycle {:>4}  {instruction:<16} X {:>3} -> {x_after:<3}  {pixel}",
+        tick.cycle, tick.x
+    )
+}
+
+#[derive(Clone, Debug, Default)]
+struct SignalStrength {
+    sum: i64,
+}
+
+impl CycleHook for SignalStrength {
+    fn during(&mut self, tick: &Tick) {
+        if tick.cycle % 40 == 20 {
+            self.sum += tick.cycle as i64 * tick.x;
+        }
+    }
+}
+
+// The sprite is centred on X, with the extra pixel on the right when its
+// width is even.
+#[derive(Clone, Debug)]
+struct Crt {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    pixels: Vec<bool>,
+}
+
+impl Crt {
+    fn new(width: usize, height: usize, sprite_width: usize) -> Crt {
+        Crt {
+            width,
+            height,
+            sprite_width,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    fn lit(&self, row: usize, col: usize) -> bool {
+        self.pixels[row * self.width + col]
+    }
+}
+
+impl CycleHook for Crt {
+    fn during(&mut self, tick: &Tick) {
+        let pos = (tick.cycle - 1) as usize;
+        if pos >= self.pixels.len() {
+            return;
+        }
+        let hpos = (pos % self.width) as i64;
+        let left = tick.x - (self.sprite_width as i64 - 1) / 2;
+        self.pixels[pos] = (left..left + self.sprite_width as i64).contains(&hpos);
+    }
+}
+
+fn render_ascii(crt: &Crt) -> String {
+    let mut out = String::new();
+    for row in 0..crt.height {
+        for col in 0..crt.width {
+            out.push(if crt.lit(row, col) { '#' } else { '.' });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn render_blocks(crt: &Crt) -> String {
+    let mut out = String::new();
+    for row in (0..crt.height).step_by(2) {
+        for col in 0..crt.width {
+            let top = crt.lit(row, col);
+            let bottom = row + 1 < crt.height && crt.lit(row + 1, col);
+            out.push(match (top, bottom) {
+                (false, false) => ' ',
+                (true, false) => '\u{2580}',
+                (false, true) => '\u{2584}',
+                (true, true) => '\u{2588}',
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Lit pixels are black.
+fn write_pbm(crt: &Crt, mut out: impl std::io::Write) -> std::io::Result<()> {
+    write!(out, "P4\n{} {}\n", crt.width, crt.height)?;
+    for row in 0..crt.height {
+        let mut bytes = vec![0u8; crt.width.div_ceil(8)];
+        for col in 0..crt.width {
+            if crt.lit(row, col) {
+                bytes[col / 8] |= 0x80 >> (col % 8);
             }
+        }
+        out.write_all(&bytes)?;
+    }
+    out.flush()
+}
+
+// Letters on the screen are separated by a blank column.
+const FONT: [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
 
-            let mut iter = l.split(' ');
-            assert_eq!(iter.next(), Some("addx"));
-            Some(iter.next().unwrap().parse().unwrap())
-        })
-        .collect();
-
-    let mut processed: Vec<i64> = vec![1, 1];
-    for i in input {
-        let prev = *processed.last().unwrap();
-        match i {
-            None => processed.push(prev),
-            Some(n) => {
-                processed.push(prev);
-                processed.push(prev + n);
+fn read_letters(pixels: &[bool], width: usize) -> Result<String, String> {
+    if pixels.len() != width * GLYPH_HEIGHT {
+        return Err(format!(
+            "can only read letters from a screen {GLYPH_HEIGHT} pixels tall"
+        ));
+    }
+
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+    for left in (0..width).step_by(GLYPH_WIDTH + 1) {
+        let right = (left + GLYPH_WIDTH).min(width);
+        let lit = |row: usize, col: usize| pixels[row * width + col];
+        let letter = FONT.iter().find(|(_, glyph)| {
+            glyph.iter().enumerate().all(|(row, line)| {
+                line.len() == right - left
+                    && line
+                        .bytes()
+                        .zip(left..right)
+                        .all(|(b, col)| (b == b'#') == lit(row, col))
+            })
+        });
+        match letter {
+            Some((c, _)) => text.push(*c),
+            None => unknown.push(format!("{left}-{}", right - 1)),
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(text)
+    } else {
+        Err(format!(
+            "unrecognised glyphs in columns {}",
+            unknown.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_program() {
+        let program = ["noop", "addx 3", "addx -5"].map(Instruction::parse);
+        let mut xs = Vec::new();
+        let mut cpu = Cpu::new(&program);
+        cpu.run_until(2, &mut [&mut |t: &Tick| xs.push(t.x)]);
+        assert_eq!(cpu.cycle, 2);
+        assert_eq!(cpu.x, 1);
+
+        cpu.run(&mut [&mut |t: &Tick| xs.push(t.x)]);
+        assert_eq!(xs, [1, 1, 1, 4, 4]);
+        assert_eq!(cpu.cycle, 5);
+        assert_eq!(cpu.x, -1);
+        assert!(cpu.halted());
+        assert_eq!(cpu.step(&mut []), None);
+    }
+
+    fn draw_letters(text: &str) -> Vec<bool> {
+        let width = text.len() * (GLYPH_WIDTH + 1);
+        let mut pixels = vec![false; width * GLYPH_HEIGHT];
+        for (i, c) in text.chars().enumerate() {
+            let (_, glyph) = FONT.iter().find(|(f, _)| *f == c).unwrap();
+            for (row, line) in glyph.iter().enumerate() {
+                for (col, b) in line.bytes().enumerate() {
+                    pixels[row * width + i * (GLYPH_WIDTH + 1) + col] = b == b'#';
+                }
             }
         }
+        pixels
     }
 
-    let p1: i64 = processed
-        .iter()
-        .enumerate()
-        .skip(20)
-        .step_by(40)
-        .map(|(c, n)| c as i64 * n)
-        .sum();
-    println!("{p1}");
+    #[test]
+    fn letters() {
+        let pixels = draw_letters("EHZFZHCZ");
+        assert_eq!(read_letters(&pixels, 40), Ok("EHZFZHCZ".to_string()));
+
+        let alphabet: String = FONT.iter().map(|(c, _)| *c).collect();
+        let pixels = draw_letters(&alphabet);
+        assert_eq!(
+            read_letters(&pixels, alphabet.len() * 5),
+            Ok(alphabet.clone())
+        );
+
+        let mut pixels = draw_letters("ABCE");
+        pixels[8] = true;
+        pixels[20 + 17] = true;
+        assert_eq!(
+            read_letters(&pixels, 20),
+            Err("unrecognised glyphs in columns 5-8, 15-18".to_string())
+        );
+        assert!(read_letters(&pixels[..20 * 5], 20).is_err());
+    }
+
+    #[test]
+    fn crt_geometry() {
+        // X stays at 1 for the whole program.
+        let program = [Instruction::Noop; 12];
+        let draw = |width, height, sprite_width| {
+            let mut crt = Crt::new(width, height, sprite_width);
+            Cpu::new(&program).run(&mut [&mut crt]);
+            crt
+        };
+
+        let crt = draw(4, 3, 3);
+        assert_eq!(render_ascii(&crt), "###.\n###.\n###.\n");
+        assert_eq!(
+            render_blocks(&crt),
+            "\u{2588}\u{2588}\u{2588} \n\u{2580}\u{2580}\u{2580} \n"
+        );
+
+        assert_eq!(render_ascii(&draw(6, 2, 1)), ".#....\n.#....\n");
+        assert_eq!(render_ascii(&draw(6, 2, 4)), "####..\n####..\n");
+
+        let mut pbm = Vec::new();
+        write_pbm(&draw(10, 1, 3), &mut pbm).unwrap();
+        assert_eq!(pbm, b"P4\n10 1\n\xe0\x00");
+    }
 
-    for pos in 0..40 * 6 {
-        let hpos = pos % 40;
-        let x = processed[pos as usize + 1];
-        if x == hpos || x == hpos + 1 || x + 1 == hpos {
-            print!("#")
-        } else {
-            print!(".");
+    #[test]
+    fn debugging() {
+        let program = ["noop", "addx 3", "addx -5"].map(Instruction::parse);
+        let crt = Crt::new(2, 2, 3);
+        let mut cpu = Cpu::new(&program);
+        let mut lines = Vec::new();
+        while let Some(tick) = cpu.step(&mut []) {
+            lines.push(trace_line(&tick, cpu.x, &crt));
         }
+        assert_eq!(
+            lines[2],
+            "cycle    3     1: addx 3     X   1 -> 4    . at (0, 1)"
+        );
+        assert_eq!(
+            lines[4],
+            "cycle    5     2: addx -5    X   4 -> -1   off screen"
+        );
 
-        if hpos == 39 {
-            println!();
+        let breakpoints = Breakpoints {
+            cycles: vec![2],
+            x_values: vec![4],
+        };
+        let mut cpu = Cpu::new(&program);
+        let mut hits = Vec::new();
+        while let Some(tick) = cpu.step(&mut []) {
+            hits.extend(breakpoints.check(&tick, cpu.x));
         }
+        assert_eq!(hits, ["breakpoint at cycle 2", "breakpoint on X = 4"]);
     }
 }
@@ -12,10 +12,228 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+
+use aoc2022::Args;
 
 fn main() {
     let lines: Vec<String> = std::io::stdin().lines().map(Result::unwrap).collect();
+    let map = parse_map(&lines);
+    let extent = map.heights.extent;
+    let config = Config::from_args(std::env::args().skip(1));
+
+    let paths = match &config.cost {
+        Some(cost) => dijkstra(&map, &config.movement, cost),
+        None => sssp(&map, &config.movement),
+    };
+    let costs = &paths.costs;
+    print_cost(*costs.at(map.start));
+
+    let best_start = map
+        .heights
+        .vals
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, h)| *h == 0)
+        .map(|(i, _)| map.heights.pos(i))
+        .min_by_key(|&p| *costs.at(p))
+        .unwrap();
+    print_cost(*costs.at(best_start));
+
+    if config.draw {
+        for start in [map.start, best_start] {
+            println!();
+            match paths.route(start) {
+                Some(route) => print!("{}", render_route(extent, &route)),
+                None => println!("no path from {start:?}"),
+            }
+        }
+    }
+
+    if config.report {
+        println!();
+        print!("{}", reachability_report(&map, &config.movement, &paths));
+    }
+
+    if let Some(path) = &config.heatmap {
+        let file = std::fs::File::create(path).unwrap();
+        let format = if path.extension().is_some_and(|e| e == "pgm") {
+            ImageFormat::Pgm
+        } else {
+            ImageFormat::Ppm
+        };
+        write_heatmap(costs, format, std::io::BufWriter::new(file)).unwrap();
+    }
+}
+
+fn print_cost(cost: Dist) {
+    match cost {
+        Dist::Fin(d) => println!("{d}"),
+        Dist::Inf => println!("no path"),
+    }
+}
+
+// Command line options:
+//
+//   --draw             draw the routes from `S` and from the best `a`
+//   --max-ascent N     most a single step may climb (default 1)
+//   --max-descent N    most a single step may descend (default unlimited)
+//   --symmetric N      most a single step may climb or descend
+//   --diagonal         allow diagonal steps
+//   --report           summarise which cells can reach the end
+//   --heatmap PATH     write distances to the end as a PGM image if PATH
+//                      ends in `.pgm`, otherwise a PPM image
+//   --weighted         each step costs 1 plus the height climbed
+//   --ascent-cost N    extra cost per unit climbed
+//   --descent-cost N   extra cost per unit descended
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Config {
+    draw: bool,
+    report: bool,
+    heatmap: Option<std::path::PathBuf>,
+    movement: Movement,
+    cost: Option<CostModel>,
+}
+
+impl Config {
+    fn from_args(args: impl Iterator<Item = String>) -> Config {
+        let mut config = Config {
+            draw: false,
+            report: false,
+            heatmap: None,
+            movement: Movement::PUZZLE,
+            cost: None,
+        };
+
+        let mut args = Args::new(args);
+        while let Some(flag) = args.next_flag() {
+            match flag {
+                "--draw" => config.draw = true,
+                "--report" => config.report = true,
+                "--heatmap" => config.heatmap = Some(args.string().into()),
+                "--max-ascent" => config.movement.climb.max_ascent = Some(args.value()),
+                "--max-descent" => config.movement.climb.max_descent = Some(args.value()),
+                "--symmetric" => config.movement.climb = ClimbRule::symmetric(args.value()),
+                "--diagonal" => config.movement.neighbourhood = Neighbourhood::Eight,
+                "--weighted" => {
+                    config.cost.get_or_insert(CostModel::UPHILL);
+                }
+                "--ascent-cost" => {
+                    config.cost.get_or_insert(CostModel::UPHILL).per_ascent = args.value();
+                }
+                "--descent-cost" => {
+                    config.cost.get_or_insert(CostModel::UPHILL).per_descent = args.value();
+                }
+                _ => args.unknown(),
+            }
+        }
+
+        config
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Movement {
+    climb: ClimbRule,
+    neighbourhood: Neighbourhood,
+}
+
+impl Movement {
+    const PUZZLE: Movement = Movement {
+        climb: ClimbRule {
+            max_ascent: Some(1),
+            max_descent: None,
+        },
+        neighbourhood: Neighbourhood::Four,
+    };
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ClimbRule {
+    // `None` means no limit.
+    max_ascent: Option<u32>,
+    max_descent: Option<u32>,
+}
+
+impl ClimbRule {
+    fn symmetric(max_change: u32) -> ClimbRule {
+        ClimbRule {
+            max_ascent: Some(max_change),
+            max_descent: Some(max_change),
+        }
+    }
+
+    fn allows(&self, from: u32, to: u32) -> bool {
+        if to > from {
+            self.max_ascent.is_none_or(|m| to - from <= m)
+        } else {
+            self.max_descent.is_none_or(|m| from - to <= m)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct CostModel {
+    base: u32,
+    per_ascent: u32,
+    per_descent: u32,
+}
+
+impl CostModel {
+    const UPHILL: CostModel = CostModel {
+        base: 1,
+        per_ascent: 1,
+        per_descent: 0,
+    };
+
+    fn step_cost(&self, from: u32, to: u32) -> u32 {
+        if to > from {
+            self.base + self.per_ascent * (to - from)
+        } else {
+            self.base + self.per_descent * (from - to)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Neighbourhood {
+    Four,
+    Eight,
+}
+
+impl Neighbourhood {
+    fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Neighbourhood::Four => &[(1, 0), (0, 1), (-1, 0), (0, -1)],
+            Neighbourhood::Eight => &[
+                (1, 0),
+                (0, 1),
+                (-1, 0),
+                (0, -1),
+                (1, 1),
+                (-1, 1),
+                (-1, -1),
+                (1, -1),
+            ],
+        }
+    }
+
+    fn neighbours(self, pos: Pos, extent: Pos) -> impl Iterator<Item = Pos> {
+        self.offsets().iter().filter_map(move |&(off_x, off_y)| {
+            let x = pos.x.checked_add_signed(off_x)?;
+            let y = pos.y.checked_add_signed(off_y)?;
+            if x >= extent.x || y >= extent.y {
+                return None;
+            }
+            Some(Pos { x, y })
+        })
+    }
+}
+
+fn parse_map(lines: &[impl AsRef<str>]) -> Map {
+    let lines: Vec<&str> = lines.iter().map(|l| l.as_ref()).collect();
     for l in &lines[1..] {
         assert_eq!(l.len(), lines[0].len());
     }
@@ -27,7 +245,7 @@ fn main() {
     let mut start = None;
     let mut end = None;
     let mut heights = vec![0; extent.x * extent.y];
-    for (i, h) in lines.iter().map(|s| s.chars()).flatten().enumerate() {
+    for (i, h) in lines.iter().flat_map(|s| s.chars()).enumerate() {
         let pos = Pos {
             x: i % extent.x,
             y: i / extent.x,
@@ -47,38 +265,78 @@ fn main() {
         };
     }
 
-    let map = Map {
+    Map {
         heights: Arr {
             vals: heights,
             extent,
         },
         start: start.unwrap(),
         end: end.unwrap(),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Paths {
+    costs: Arr<Dist>,
+    // The next step towards the end from each cell.
+    next: Arr<Option<Pos>>,
+    end: Pos,
+}
+
+impl Paths {
+    fn route(&self, start: Pos) -> Option<Vec<Pos>> {
+        self.costs.at(start).fin()?;
+        let mut route = vec![start];
+        while let Some(next) = *self.next.at(*route.last().unwrap()) {
+            route.push(next);
+        }
+        assert_eq!(*route.last().unwrap(), self.end);
+        Some(route)
+    }
+}
+
+fn render_route(extent: Pos, route: &[Pos]) -> String {
+    use std::cmp::Ordering::*;
+
+    let mut grid = Arr {
+        vals: vec!['.'; extent.x * extent.y],
+        extent,
     };
+    for step in route.windows(2) {
+        let (from, to) = (step[0], step[1]);
+        *grid.at_mut(from) = match (to.x.cmp(&from.x), to.y.cmp(&from.y)) {
+            (Greater, Equal) => '>',
+            (Less, Equal) => '<',
+            (Equal, Greater) => 'v',
+            (Equal, Less) => '^',
+            (Greater, Greater) => '↘',
+            (Less, Greater) => '↙',
+            (Greater, Less) => '↗',
+            (Less, Less) => '↖',
+            (Equal, Equal) => unreachable!(),
+        };
+    }
+    if let Some(&end) = route.last() {
+        *grid.at_mut(end) = 'E';
+    }
 
-    let costs = sssp(&map);
-    println!("{}", costs.at(map.start).fin().unwrap());
-    println!(
-        "{}",
-        map.heights
-            .vals
-            .iter()
-            .copied()
-            .enumerate()
-            .filter(|(_, h)| *h == 0)
-            .map(|(i, _)| costs.vals[i])
-            .min()
-            .unwrap()
-            .fin()
-            .unwrap()
-    );
-}
-
-fn sssp(map: &Map) -> Arr<Dist> {
+    let mut out = String::new();
+    for row in grid.vals.chunks(extent.x) {
+        out.extend(row);
+        out.push('\n');
+    }
+    out
+}
+
+fn sssp(map: &Map, movement: &Movement) -> Paths {
     let mut costs = Arr {
         vals: vec![Dist::Inf; map.heights.vals.len()],
         extent: map.heights.extent,
     };
+    let mut next_step = Arr {
+        vals: vec![None; map.heights.vals.len()],
+        extent: map.heights.extent,
+    };
 
     // Breadth-first search since every edge has cost 1.
     let mut visited = HashSet::<Pos>::new();
@@ -87,23 +345,10 @@ fn sssp(map: &Map) -> Arr<Dist> {
     while let Some((p, d)) = queue.pop_front() {
         let h = *map.heights.at(p);
         *costs.at_mut(p) = Dist::Fin(d);
-        for (off_x, off_y) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
-            let x = p.x as isize + off_x;
-            let y = p.y as isize + off_y;
-            if x < 0 || y < 0 {
-                continue;
-            }
-
-            let x = x as usize;
-            let y = y as usize;
-
-            if x >= map.heights.extent.x || y >= map.heights.extent.y {
-                continue;
-            }
-
-            let next = Pos { x, y };
+        for next in movement.neighbourhood.neighbours(p, map.heights.extent) {
+            // The search runs backwards, so this is a step from `next` to `p`.
             let h_next = *map.heights.at(next);
-            if h_next + 1 < h {
+            if !movement.climb.allows(h_next, h) {
                 continue;
             }
 
@@ -116,11 +361,195 @@ fn sssp(map: &Map) -> Arr<Dist> {
             }
 
             visited.insert(next);
+            *next_step.at_mut(next) = Some(p);
             queue.push_back((next, d + 1));
         }
     }
 
-    costs
+    Paths {
+        costs,
+        next: next_step,
+        end: map.end,
+    }
+}
+
+fn dijkstra(map: &Map, movement: &Movement, cost: &CostModel) -> Paths {
+    let mut costs = Arr {
+        vals: vec![Dist::Inf; map.heights.vals.len()],
+        extent: map.heights.extent,
+    };
+    let mut next_step = Arr {
+        vals: vec![None; map.heights.vals.len()],
+        extent: map.heights.extent,
+    };
+
+    let mut queue = BinaryHeap::<Reverse<(u32, usize, usize)>>::new();
+    *costs.at_mut(map.end) = Dist::Fin(0);
+    queue.push(Reverse((0, map.end.x, map.end.y)));
+    while let Some(Reverse((d, x, y))) = queue.pop() {
+        let p = Pos { x, y };
+        if Dist::Fin(d) > *costs.at(p) {
+            // Already settled via a cheaper route.
+            continue;
+        }
+
+        let h = *map.heights.at(p);
+        for next in movement.neighbourhood.neighbours(p, map.heights.extent) {
+            let h_next = *map.heights.at(next);
+            if !movement.climb.allows(h_next, h) {
+                continue;
+            }
+
+            let d_next = d + cost.step_cost(h_next, h);
+            if Dist::Fin(d_next) < *costs.at(next) {
+                *costs.at_mut(next) = Dist::Fin(d_next);
+                *next_step.at_mut(next) = Some(p);
+                queue.push(Reverse((d_next, next.x, next.y)));
+            }
+        }
+    }
+
+    Paths {
+        costs,
+        next: next_step,
+        end: map.end,
+    }
+}
+
+// Joins cells by steps the climbing rule allows in both directions. This is
+// stricter than mutual reachability: two cells may each reach the other only
+// by way of one-way steps, and still land in different regions.
+fn regions(map: &Map, movement: &Movement) -> (Arr<usize>, usize) {
+    let extent = map.heights.extent;
+    let mut region = Arr {
+        vals: vec![usize::MAX; map.heights.vals.len()],
+        extent,
+    };
+
+    let mut num_regions = 0;
+    for i in 0..region.vals.len() {
+        if region.vals[i] != usize::MAX {
+            continue;
+        }
+
+        let mut stack = vec![region.pos(i)];
+        region.vals[i] = num_regions;
+        while let Some(p) = stack.pop() {
+            let h = *map.heights.at(p);
+            for next in movement.neighbourhood.neighbours(p, extent) {
+                let h_next = *map.heights.at(next);
+                if *region.at(next) == usize::MAX
+                    && movement.climb.allows(h, h_next)
+                    && movement.climb.allows(h_next, h)
+                {
+                    *region.at_mut(next) = num_regions;
+                    stack.push(next);
+                }
+            }
+        }
+        num_regions += 1;
+    }
+
+    (region, num_regions)
+}
+
+fn reachability_report(map: &Map, movement: &Movement, paths: &Paths) -> String {
+    use std::fmt::Write;
+
+    let costs = &paths.costs;
+    let reachable = costs.vals.iter().filter(|d| d.fin().is_some()).count();
+    let lowest: Vec<Pos> = (0..map.heights.vals.len())
+        .filter(|&i| map.heights.vals[i] == 0)
+        .map(|i| map.heights.pos(i))
+        .collect();
+    let (lowest_reachable, lowest_unreachable): (Vec<Pos>, Vec<Pos>) =
+        lowest.iter().partition(|&&p| costs.at(p).fin().is_some());
+    let list = |cells: &[Pos]| {
+        cells
+            .iter()
+            .map(|p| format!(" {},{}", p.x, p.y))
+            .collect::<String>()
+    };
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{reachable} of {} cells can reach the end",
+        costs.vals.len()
+    )
+    .unwrap();
+    match costs.at(map.start) {
+        Dist::Fin(d) => writeln!(out, "S reaches the end in {d}").unwrap(),
+        Dist::Inf => writeln!(out, "S cannot reach the end").unwrap(),
+    }
+    writeln!(
+        out,
+        "{} of {} lowest cells can reach the end",
+        lowest_reachable.len(),
+        lowest.len()
+    )
+    .unwrap();
+    writeln!(out, "  reachable:{}", list(&lowest_reachable)).unwrap();
+    writeln!(out, "  unreachable:{}", list(&lowest_unreachable)).unwrap();
+
+    let (region, num_regions) = regions(map, movement);
+    let mut sizes = vec![0; num_regions];
+    for &r in &region.vals {
+        sizes[r] += 1;
+    }
+    let mut by_size: Vec<usize> = (0..num_regions).collect();
+    by_size.sort_by_key(|&r| Reverse(sizes[r]));
+    writeln!(out, "{num_regions} regions joined by two-way steps").unwrap();
+    for &r in by_size.iter().take(10) {
+        let cells = (0..region.vals.len()).filter(|&i| region.vals[i] == r);
+        let reaches_end = cells.clone().any(|i| costs.vals[i].fin().is_some());
+        let contains_end = *region.at(map.end) == r;
+        writeln!(
+            out,
+            "  region {r}: {} cells{}{}",
+            sizes[r],
+            if contains_end {
+                ", contains the end"
+            } else {
+                ""
+            },
+            if reaches_end { "" } else { ", unreachable" }
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ImageFormat {
+    Pgm,
+    Ppm,
+}
+
+// Cells near the end are bright and fade with distance. Cells that can't
+// reach the end are black, or red in a PPM.
+fn write_heatmap(
+    costs: &Arr<Dist>,
+    format: ImageFormat,
+    mut out: impl std::io::Write,
+) -> std::io::Result<()> {
+    let max = costs.vals.iter().filter_map(|d| d.fin()).max().unwrap_or(0);
+    let shade = |d: u32| (255 - (d as u64 * 215 / max.max(1) as u64)) as u8;
+
+    let magic = match format {
+        ImageFormat::Pgm => "P5",
+        ImageFormat::Ppm => "P6",
+    };
+    write!(out, "{magic}\n{} {}\n255\n", costs.extent.x, costs.extent.y)?;
+    for d in &costs.vals {
+        match (format, d) {
+            (ImageFormat::Pgm, Dist::Fin(d)) => out.write_all(&[shade(*d)])?,
+            (ImageFormat::Pgm, Dist::Inf) => out.write_all(&[0])?,
+            (ImageFormat::Ppm, Dist::Fin(d)) => out.write_all(&[shade(*d); 3])?,
+            (ImageFormat::Ppm, Dist::Inf) => out.write_all(&[160, 0, 0])?,
+        }
+    }
+    out.flush()
 }
 
 #[derive(Clone, Debug)]
@@ -144,6 +573,13 @@ impl<T> Arr<T> {
     fn at_mut(&mut self, pos: Pos) -> &mut T {
         &mut self.vals[pos.x + pos.y * self.extent.x]
     }
+
+    fn pos(&self, index: usize) -> Pos {
+        Pos {
+            x: index % self.extent.x,
+            y: index / self.extent.x,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -166,3 +602,105 @@ impl Dist {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: [&str; 5] = ["Sabqponm", "abcryxxl", "accszExk", "acctuvwj", "abdefghi"];
+
+    #[test]
+    fn sample_route() {
+        let map = parse_map(&SAMPLE);
+        let paths = sssp(&map, &Movement::PUZZLE);
+        let route = paths.route(map.start).unwrap();
+        assert_eq!(route.len(), 32);
+        assert_eq!(route[0], map.start);
+        for step in route.windows(2) {
+            let (from, to) = (step[0], step[1]);
+            assert_eq!(from.x.abs_diff(to.x) + from.y.abs_diff(to.y), 1);
+            assert!(*map.heights.at(to) <= *map.heights.at(from) + 1);
+        }
+
+        let rendered = render_route(map.heights.extent, &route);
+        assert_eq!(rendered.lines().nth(2).unwrap().chars().nth(5), Some('E'));
+        assert_eq!(rendered.chars().filter(|c| "^v<>".contains(*c)).count(), 31);
+    }
+
+    #[test]
+    fn climb_rules() {
+        let puzzle = Movement::PUZZLE.climb;
+        assert!(puzzle.allows(3, 4));
+        assert!(!puzzle.allows(3, 5));
+        assert!(puzzle.allows(25, 0));
+
+        let two = ClimbRule::symmetric(2);
+        assert!(two.allows(3, 5));
+        assert!(two.allows(5, 3));
+        assert!(!two.allows(6, 3));
+
+        let map = parse_map(&SAMPLE);
+        let looser = Movement {
+            climb: ClimbRule {
+                max_ascent: Some(2),
+                max_descent: None,
+            },
+            neighbourhood: Neighbourhood::Four,
+        };
+        let strict = sssp(&map, &Movement::PUZZLE).costs;
+        let loose = sssp(&map, &looser).costs;
+        assert!(loose.at(map.start) < strict.at(map.start));
+
+        let diagonal = Movement {
+            neighbourhood: Neighbourhood::Eight,
+            ..Movement::PUZZLE
+        };
+        assert_eq!(sssp(&map, &diagonal).costs.at(map.start).fin(), Some(27));
+    }
+
+    #[test]
+    fn weighted_costs() {
+        let map = parse_map(&SAMPLE);
+
+        // With no extra cost for climbing, Dijkstra agrees with the BFS.
+        let flat = CostModel {
+            base: 1,
+            per_ascent: 0,
+            per_descent: 0,
+        };
+        let bfs = sssp(&map, &Movement::PUZZLE);
+        let weighted = dijkstra(&map, &Movement::PUZZLE, &flat);
+        assert_eq!(bfs.costs.vals, weighted.costs.vals);
+
+        // The cheapest route never descends, so it costs one per step plus
+        // the 25 units from `S` up to `E`.
+        let uphill = dijkstra(&map, &Movement::PUZZLE, &CostModel::UPHILL);
+        let route = uphill.route(map.start).unwrap();
+        let total: u32 = route
+            .windows(2)
+            .map(|s| CostModel::UPHILL.step_cost(*map.heights.at(s[0]), *map.heights.at(s[1])))
+            .sum();
+        assert_eq!(uphill.costs.at(map.start).fin(), Some(total));
+        assert_eq!(total, 31 + 25);
+    }
+
+    #[test]
+    fn unreachable_summit() {
+        // A cliff cuts the summit off from everything else.
+        let map = parse_map(&["Sabcz", "abcdz", "zzzzE"]);
+        let paths = sssp(&map, &Movement::PUZZLE);
+        assert_eq!(*paths.costs.at(map.start), Dist::Inf);
+        assert_eq!(paths.route(map.start), None);
+
+        let report = reachability_report(&map, &Movement::PUZZLE, &paths);
+        assert!(report.contains("S cannot reach the end"), "{report}");
+        assert!(report.contains("0 of 3 lowest cells"), "{report}");
+        assert!(report.contains("  reachable:\n"), "{report}");
+        assert!(report.contains("  unreachable: 0,0 1,0 0,1\n"), "{report}");
+
+        let (region, num_regions) = regions(&map, &Movement::PUZZLE);
+        assert_eq!(num_regions, 2);
+        assert_eq!(region.at(map.start), region.at(Pos { x: 3, y: 1 }));
+        assert_eq!(region.at(map.end), region.at(Pos { x: 0, y: 2 }));
+    }
+}
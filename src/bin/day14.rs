@@ -12,63 +12,455 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use aoc2022::Args;
 use nom::Finish;
+use std::io::Read;
 
 fn main() {
-    let paths: Vec<RockPath> = std::io::stdin()
-        .lines()
-        .map(|line| parse_path(&line.unwrap()).finish().unwrap().1)
-        .collect();
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let paths = parse_paths(&input).unwrap_or_else(|e| panic!("{e}"));
 
-    let floor_y = paths
-        .iter()
-        .flat_map(|p| &p.points)
-        .map(|pt| pt.y)
-        .max()
-        .unwrap()
-        + 2;
-    let mut map = draw_map(&paths);
+    let floor_y = floor_y(&paths);
+    let p1_rules = SandRules::puzzle(None);
+    let p2_rules = SandRules::puzzle(Some(floor_y));
 
-    let mut p1_map = map.clone();
-    let p1 = match compute(CavePoint { x: 500, y: 0 }, &mut p1_map, None) {
+    let mut p1_map = draw_map(&paths, &p1_rules);
+    let p1 = match compute(SOURCE, &mut p1_map, &p1_rules) {
         ComputeResult::Flow(x) => x,
         _ => unreachable!(),
     };
     println!("{}", p1);
 
-    let p2 = match compute(CavePoint { x: 500, y: 0 }, &mut map, Some(floor_y)) {
+    let mut map = draw_map(&paths, &p2_rules);
+    let p2 = match compute(SOURCE, &mut map, &p2_rules) {
         ComputeResult::Rest(x) => x,
         _ => unreachable!(),
     };
     println!("{}", p2);
+
+    let config = Config::from_args(std::env::args().skip(1));
+    let runs = match config.custom_rules() {
+        Some(rules) => vec![("custom", rules)],
+        None if config.frames_dir.is_some() || config.print => {
+            vec![("part1", p1_rules), ("part2", p2_rules)]
+        }
+        None => return,
+    };
+    for (name, rules) in runs {
+        let mut map = draw_map(&paths, &rules);
+        let mut sim = Simulation::new(&rules);
+        match &config.frames_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(dir).unwrap();
+                export_frames(&mut map, &mut sim, &dir.join(name), &config).unwrap();
+            }
+            None => while sim.step(&mut map).is_some() {},
+        }
+
+        println!(
+            "{name}: {} grains at rest ({:?}){}",
+            sim.dropped.iter().sum::<u32>(),
+            sim.dropped,
+            if sim.reached_abyss {
+                ", sand reached the abyss"
+            } else {
+                ""
+            }
+        );
+        if config.print {
+            print!("{map}");
+        }
+    }
 }
 
-fn compute(pos: CavePoint, map: &mut CaveMap, floor_y: Option<u32>) -> ComputeResult {
+// Command line options:
+//
+//   --frames DIR      write frames of the cave filling up
+//   --every N         only write a frame after every N-th grain
+//   --format FORMAT   frame format, `ascii` (default) or `ppm`
+//   --print           print the cave once sand stops
+//   --source X,Y[,N]  add a source letting in at most N grains; repeatable
+//   --moves DX,DY;... moves a grain tries in order, with DY > 0
+//   --floor Y         put an infinite floor at depth Y
+//
+// Any of the last three simulate that variant instead of parts 1 and 2.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Config {
+    frames_dir: Option<std::path::PathBuf>,
+    every: u32,
+    format: FrameFormat,
+    print: bool,
+    sources: Vec<Source>,
+    moves: Option<Vec<(i32, i32)>>,
+    floor_y: Option<i32>,
+}
+
+impl Config {
+    fn from_args(args: impl Iterator<Item = String>) -> Config {
+        let mut config = Config {
+            frames_dir: None,
+            every: 1,
+            format: FrameFormat::Ascii,
+            print: false,
+            sources: Vec::new(),
+            moves: None,
+            floor_y: None,
+        };
+
+        let mut args = Args::new(args);
+        let numbers = |args: &Args<_>, v: &str| -> Vec<i32> {
+            v.split(',').map(|n| args.parse(n.trim())).collect()
+        };
+        while let Some(flag) = args.next_flag() {
+            match flag {
+                "--frames" => config.frames_dir = Some(args.string().into()),
+                "--every" => {
+                    let v = args.string();
+                    config.every = v
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .unwrap_or_else(|| panic!("bad value for --every: {v:?}"));
+                }
+                "--format" => {
+                    config.format = match args.string().as_str() {
+                        "ascii" => FrameFormat::Ascii,
+                        "ppm" => FrameFormat::Ppm,
+                        f => panic!("unknown frame format {f:?}"),
+                    }
+                }
+                "--print" => config.print = true,
+                "--source" => {
+                    let v = args.string();
+                    let (pos, budget) = match numbers(&args, &v)[..] {
+                        [x, y] => (CavePoint { x, y }, None),
+                        [x, y, n] if n >= 0 => (CavePoint { x, y }, Some(n as u32)),
+                        _ => panic!("--source needs X,Y or X,Y,N, got {v:?}"),
+                    };
+                    if pos.y < 0 {
+                        panic!("--source must not be above the top of the cave, got {v:?}");
+                    }
+                    config.sources.push(Source { pos, budget });
+                }
+                "--moves" => {
+                    let v = args.string();
+                    let moves = v
+                        .split(';')
+                        .map(|m| match numbers(&args, m)[..] {
+                            [dx, dy] if dy > 0 => (dx, dy),
+                            _ => panic!("--moves needs DX,DY pairs with DY > 0, got {v:?}"),
+                        })
+                        .collect();
+                    config.moves = Some(moves);
+                }
+                "--floor" => config.floor_y = Some(args.value()),
+                _ => args.unknown(),
+            }
+        }
+
+        if let Some(f) = config.floor_y {
+            if let Some(s) = config.sources.iter().find(|s| s.pos.y >= f) {
+                panic!(
+                    "--source {},{} is not above the floor at {f}",
+                    s.pos.x, s.pos.y
+                );
+            }
+        }
+
+        config
+    }
+
+    fn custom_rules(&self) -> Option<SandRules> {
+        if self.sources.is_empty() && self.moves.is_none() && self.floor_y.is_none() {
+            return None;
+        }
+
+        let mut rules = SandRules::puzzle(self.floor_y);
+        if !self.sources.is_empty() {
+            rules.sources = self.sources.clone();
+        }
+        if let Some(moves) = &self.moves {
+            rules.moves = moves.clone();
+        }
+        Some(rules)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Source {
+    pos: CavePoint,
+    budget: Option<u32>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct SandRules {
+    sources: Vec<Source>,
+    // Tried in order; the grain rests once none of them lead to air.
+    moves: Vec<(i32, i32)>,
+    floor_y: Option<i32>,
+}
+
+impl SandRules {
+    fn puzzle(floor_y: Option<i32>) -> SandRules {
+        SandRules {
+            sources: vec![Source {
+                pos: SOURCE,
+                budget: None,
+            }],
+            moves: vec![(0, 1), (-1, 1), (1, 1)],
+            floor_y,
+        }
+    }
+}
+
+// Depth-first search over the cells sand can reach: a cell fills once every
+// cell a grain could move to from it is filled. Uses its own stack since paths
+// are as long as the cave is deep.
+fn compute(source: CavePoint, map: &mut CaveMap, rules: &SandRules) -> ComputeResult {
     use ComputeResult::*;
 
-    if let Some(floor_y) = floor_y {
-        if pos.y == floor_y {
-            return Rest(0);
+    let mut settled = 0;
+
+    // Air cells being filled, and which of the moves to try next.
+    let mut stack: Vec<(CavePoint, usize)> = Vec::new();
+    match probe(source, map, rules.floor_y) {
+        Probe::Blocked => return Rest(0),
+        Probe::Abyss => return Flow(0),
+        Probe::Open => stack.push((source, 0)),
+    }
+
+    while let Some(frame) = stack.last_mut() {
+        let (pos, next) = *frame;
+        if next == rules.moves.len() {
+            map.set(pos, CaveElem::Sand);
+            settled += 1;
+            stack.pop();
+            continue;
+        }
+        frame.1 += 1;
+
+        let below = pos.offset(rules.moves[next]);
+        match probe(below, map, rules.floor_y) {
+            Probe::Blocked => (),
+            Probe::Abyss => return Flow(settled),
+            Probe::Open => stack.push((below, 0)),
         }
-    } else if pos.x as usize >= CAVE_WIDTH || pos.y as usize >= CAVE_HEIGHT {
-        return Flow(0);
     }
 
-    if map.map[pos.x as usize][pos.y as usize] != CaveElem::Air {
-        return Rest(0);
+    Rest(settled)
+}
+
+enum Probe {
+    Open,
+    Blocked,
+    Abyss,
+}
+
+fn probe(pos: CavePoint, map: &CaveMap, floor_y: Option<i32>) -> Probe {
+    if floor_y.is_some_and(|f| pos.y >= f) {
+        return Probe::Blocked;
+    }
+    match map.get(pos) {
+        None => Probe::Abyss,
+        Some(CaveElem::Air) => Probe::Open,
+        Some(_) => Probe::Blocked,
+    }
+}
+
+// Each grain follows the previous one's path up to where it came to rest, so
+// the path is kept between grains.
+struct Pourer {
+    source: CavePoint,
+    path: Vec<CavePoint>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Grain {
+    Rest(CavePoint),
+    Abyss,
+    Blocked,
+}
+
+impl Pourer {
+    fn new(source: CavePoint) -> Pourer {
+        Pourer {
+            source,
+            path: Vec::new(),
+        }
+    }
+
+    fn revalidate(&mut self, map: &CaveMap) {
+        if let Some(i) = self
+            .path
+            .iter()
+            .position(|&pos| map.get(pos) != Some(CaveElem::Air))
+        {
+            self.path.truncate(i);
+        }
+    }
+
+    fn drop_grain(&mut self, map: &mut CaveMap, rules: &SandRules) -> Grain {
+        if self.path.is_empty() {
+            match probe(self.source, map, rules.floor_y) {
+                Probe::Open => self.path.push(self.source),
+                Probe::Blocked => return Grain::Blocked,
+                Probe::Abyss => return Grain::Abyss,
+            }
+        }
+
+        loop {
+            let pos = *self.path.last().unwrap();
+            let next = rules
+                .moves
+                .iter()
+                .map(|&m| pos.offset(m))
+                .map(|below| (below, probe(below, map, rules.floor_y)))
+                .find(|(_, probe)| !matches!(probe, Probe::Blocked));
+            match next {
+                Some((_, Probe::Abyss)) => return Grain::Abyss,
+                Some((below, _)) => self.path.push(below),
+                None => {
+                    self.path.pop();
+                    map.set(pos, CaveElem::Sand);
+                    return Grain::Rest(pos);
+                }
+            }
+        }
+    }
+}
+
+// Sources take turns. Ends when a grain falls into the abyss, or every source
+// is blocked or out of budget.
+struct Simulation<'a> {
+    rules: &'a SandRules,
+    pourers: Vec<Pourer>,
+    dropped: Vec<u32>,
+    exhausted: Vec<bool>,
+    next: usize,
+    reached_abyss: bool,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(rules: &'a SandRules) -> Simulation<'a> {
+        Simulation {
+            rules,
+            pourers: rules.sources.iter().map(|s| Pourer::new(s.pos)).collect(),
+            dropped: vec![0; rules.sources.len()],
+            exhausted: vec![false; rules.sources.len()],
+            next: 0,
+            reached_abyss: false,
+        }
     }
 
-    let mut total = 0;
+    fn step(&mut self, map: &mut CaveMap) -> Option<(usize, CavePoint)> {
+        let num_sources = self.pourers.len();
+        for _ in 0..num_sources {
+            if self.reached_abyss {
+                return None;
+            }
+
+            let i = self.next;
+            self.next = (i + 1) % num_sources;
+            if self.exhausted[i] {
+                continue;
+            }
+            if self.rules.sources[i].budget == Some(self.dropped[i]) {
+                self.exhausted[i] = true;
+                continue;
+            }
+
+            // Grains from other sources may have landed on this source's path.
+            if num_sources > 1 {
+                self.pourers[i].revalidate(map);
+            }
+            match self.pourers[i].drop_grain(map, self.rules) {
+                Grain::Rest(pos) => {
+                    self.dropped[i] += 1;
+                    return Some((i, pos));
+                }
+                Grain::Abyss => self.reached_abyss = true,
+                Grain::Blocked => self.exhausted[i] = true,
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FrameFormat {
+    Ascii,
+    Ppm,
+}
 
-    for x in [pos.x, pos.x - 1, pos.x + 1] {
-        total += match compute(CavePoint { x, y: pos.y + 1 }, map, floor_y) {
-            Rest(x) => x,
-            Flow(x) => return Flow(total + x),
+fn export_frames(
+    map: &mut CaveMap,
+    sim: &mut Simulation,
+    prefix: &std::path::Path,
+    config: &Config,
+) -> std::io::Result<()> {
+    let write = |map: &CaveMap, grains: u32| {
+        let ext = match config.format {
+            FrameFormat::Ascii => "txt",
+            FrameFormat::Ppm => "ppm",
         };
+        let mut path = prefix.as_os_str().to_owned();
+        path.push(format!("_{grains:06}.{ext}"));
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        match config.format {
+            FrameFormat::Ascii => write_ascii_frame(map, &mut out),
+            FrameFormat::Ppm => write_ppm_frame(map, &mut out),
+        }
+    };
+
+    let mut grains = 0;
+    write(map, grains)?;
+    while sim.step(map).is_some() {
+        grains += 1;
+        if grains % config.every == 0 {
+            write(map, grains)?;
+        }
+    }
+    if grains % config.every != 0 {
+        write(map, grains)?;
+    }
+    Ok(())
+}
+
+fn write_ascii_frame(map: &CaveMap, mut out: impl std::io::Write) -> std::io::Result<()> {
+    for y in 0..map.height as i32 {
+        let row: String = (0..map.width as i32)
+            .map(|col| {
+                let pos = CavePoint {
+                    x: map.min_x + col,
+                    y,
+                };
+                map.cell_char(pos).unwrap()
+            })
+            .collect();
+        writeln!(out, "{row}")?;
     }
+    out.flush()
+}
 
-    map.map[pos.x as usize][pos.y as usize] = CaveElem::Sand;
-    Rest(total + 1)
+fn write_ppm_frame(map: &CaveMap, mut out: impl std::io::Write) -> std::io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", map.width, map.height)?;
+    for y in 0..map.height as i32 {
+        for col in 0..map.width as i32 {
+            let pos = CavePoint {
+                x: map.min_x + col,
+                y,
+            };
+            let rgb: [u8; 3] = match map.get(pos).unwrap() {
+                CaveElem::Air if map.sources.contains(&pos) => [255, 40, 40],
+                CaveElem::Air => [20, 20, 30],
+                CaveElem::Rock => [120, 110, 100],
+                CaveElem::Sand => [230, 200, 90],
+            };
+            out.write_all(&rgb)?;
+        }
+    }
+    out.flush()
 }
 
 enum ComputeResult {
@@ -78,13 +470,136 @@ enum ComputeResult {
     Flow(u32),
 }
 
-#[derive(Clone, Copy, Debug)]
+const SOURCE: CavePoint = CavePoint { x: 500, y: 0 };
+
+// Covers the part of the cave sand can reach. Everything outside is abyss.
+#[derive(Clone, Debug)]
 struct CaveMap {
-    map: [[CaveElem; CAVE_HEIGHT]; CAVE_WIDTH],
+    min_x: i32,
+    width: usize,
+    height: usize,
+    // Column-major.
+    map: Vec<CaveElem>,
+    sources: Vec<CavePoint>,
+}
+
+impl CaveMap {
+    fn new(min_x: i32, width: usize, height: usize) -> CaveMap {
+        CaveMap {
+            min_x,
+            width,
+            height,
+            map: vec![CaveElem::Air; width * height],
+            sources: Vec::new(),
+        }
+    }
+
+    #[cfg(test)]
+    fn parse(picture: &str, origin: CavePoint) -> Result<CaveMap, String> {
+        let rows: Vec<&str> = picture.lines().collect();
+        let width = rows.first().map_or(0, |r| r.chars().count());
+        if origin.y < 0 {
+            return Err(format!("origin {origin:?} is above the top of the cave"));
+        }
+
+        let mut map = CaveMap::new(origin.x, width, origin.y as usize + rows.len());
+        for (dy, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(format!(
+                    "row {dy} has {} columns, expected {width}",
+                    row.chars().count()
+                ));
+            }
+            for (dx, c) in row.chars().enumerate() {
+                let pos = CavePoint {
+                    x: origin.x + dx as i32,
+                    y: origin.y + dy as i32,
+                };
+                let elem = match c {
+                    '.' => CaveElem::Air,
+                    '+' => {
+                        map.sources.push(pos);
+                        CaveElem::Air
+                    }
+                    '#' => CaveElem::Rock,
+                    'o' => CaveElem::Sand,
+                    _ => return Err(format!("unexpected {c:?} at row {dy}, column {dx}")),
+                };
+                map.set(pos, elem);
+            }
+        }
+        Ok(map)
+    }
+
+    fn bounding_box(&self) -> Option<(CavePoint, CavePoint)> {
+        let occupied = (0..self.width)
+            .flat_map(|col| (0..self.height).map(move |row| (col, row)))
+            .filter(|&(col, row)| self.map[col * self.height + row] != CaveElem::Air)
+            .map(|(col, row)| CavePoint {
+                x: self.min_x + col as i32,
+                y: row as i32,
+            })
+            .chain(self.sources.iter().copied());
+
+        occupied.fold(None, |bounds, pos| match bounds {
+            None => Some((pos, pos)),
+            Some((min, max)) => Some((
+                CavePoint {
+                    x: min.x.min(pos.x),
+                    y: min.y.min(pos.y),
+                },
+                CavePoint {
+                    x: max.x.max(pos.x),
+                    y: max.y.max(pos.y),
+                },
+            )),
+        })
+    }
+
+    fn cell_char(&self, pos: CavePoint) -> Option<char> {
+        Some(match self.get(pos)? {
+            CaveElem::Air if self.sources.contains(&pos) => '+',
+            CaveElem::Air => '.',
+            CaveElem::Rock => '#',
+            CaveElem::Sand => 'o',
+        })
+    }
+
+    fn index(&self, pos: CavePoint) -> Option<usize> {
+        let col = usize::try_from(pos.x - self.min_x).ok()?;
+        let row = usize::try_from(pos.y).ok()?;
+        if col >= self.width || row >= self.height {
+            return None;
+        }
+        Some(col * self.height + row)
+    }
+
+    fn get(&self, pos: CavePoint) -> Option<CaveElem> {
+        self.index(pos).map(|i| self.map[i])
+    }
+
+    fn set(&mut self, pos: CavePoint, elem: CaveElem) {
+        let i = self
+            .index(pos)
+            .unwrap_or_else(|| panic!("{pos:?} is outside the cave"));
+        self.map[i] = elem;
+    }
 }
 
-const CAVE_WIDTH: usize = 1000;
-const CAVE_HEIGHT: usize = 200;
+impl std::fmt::Display for CaveMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((min, max)) = self.bounding_box() else {
+            return Ok(());
+        };
+        for y in min.y..=max.y {
+            let row: String = (min.x..=max.x)
+                .map(|x| self.cell_char(CavePoint { x, y }).unwrap_or('.'))
+                .collect();
+            writeln!(f, "{row}")?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum CaveElem {
@@ -93,34 +608,86 @@ enum CaveElem {
     Sand,
 }
 
-fn draw_map(paths: &[RockPath]) -> CaveMap {
-    let mut map = [[CaveElem::Air; CAVE_HEIGHT]; CAVE_WIDTH];
+fn floor_y(paths: &[RockPath]) -> i32 {
+    paths
+        .iter()
+        .flat_map(|p| &p.points)
+        .map(|pt| pt.y)
+        .max()
+        .unwrap()
+        + 2
+}
+
+fn draw_map(paths: &[RockPath], rules: &SandRules) -> CaveMap {
+    // Without a floor, the abyss starts below the lowest rock, or below the
+    // lowest source so that it is drawn too. Any rock below a floor is still
+    // drawn, though sand can't reach it.
+    let height = rules
+        .sources
+        .iter()
+        .map(|s| s.pos.y + 1)
+        .chain([floor_y(paths), rules.floor_y.unwrap_or(0)])
+        .max()
+        .unwrap();
+
+    // Sand from each source stays within a triangle below it, plus a column
+    // either side to fall past the outermost rocks.
+    let max_dx = rules.moves.iter().map(|m| m.0.abs()).max().unwrap_or(0);
+    let reach = |s: &Source| max_dx * (height - s.pos.y).max(0);
+    let rock_xs = paths.iter().flat_map(|p| &p.points).map(|pt| pt.x);
+    let min_x = rock_xs
+        .clone()
+        .chain(rules.sources.iter().map(|s| s.pos.x - reach(s)))
+        .min()
+        .unwrap()
+        - max_dx;
+    let max_x = rock_xs
+        .chain(rules.sources.iter().map(|s| s.pos.x + reach(s)))
+        .max()
+        .unwrap()
+        + max_dx;
+
+    let mut map = CaveMap::new(min_x, (max_x - min_x + 1) as usize, height as usize);
+    map.sources = rules.sources.iter().map(|s| s.pos).collect();
     for path in paths {
+        if let [p] = path.points[..] {
+            map.set(p, CaveElem::Rock);
+        }
         for win in path.points.windows(2) {
-            let (mut p1, mut p2) = match win {
-                [p1, p2] => (p1, p2),
-                _ => unreachable!(),
-            };
-
-            if p1.x != p2.x {
-                if p1.x > p2.x {
-                    std::mem::swap(&mut p1, &mut p2);
-                }
-                for x in p1.x..=p2.x {
-                    map[x as usize][p1.y as usize] = CaveElem::Rock;
-                }
-            } else if p1.y != p2.y {
-                if p1.y > p2.y {
-                    std::mem::swap(&mut p1, &mut p2);
-                }
-                for y in p1.y..=p2.y {
-                    map[p1.x as usize][y as usize] = CaveElem::Rock;
-                }
+            for p in rasterise(win[0], win[1]) {
+                map.set(p, CaveElem::Rock);
             }
         }
     }
 
-    CaveMap { map }
+    map
+}
+
+// Bresenham's line, including both ends.
+fn rasterise(p1: CavePoint, p2: CavePoint) -> Vec<CavePoint> {
+    let dx = (p2.x - p1.x).abs();
+    let dy = -(p2.y - p1.y).abs();
+    let step_x = (p2.x - p1.x).signum();
+    let step_y = (p2.y - p1.y).signum();
+
+    let mut cells = Vec::new();
+    let mut pos = p1;
+    let mut err = dx + dy;
+    loop {
+        cells.push(pos);
+        if pos == p2 {
+            return cells;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            pos.x += step_x;
+        }
+        if e2 <= dx {
+            err += dx;
+            pos.y += step_y;
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -128,10 +695,50 @@ struct RockPath {
     points: Vec<CavePoint>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 struct CavePoint {
-    x: u32,
-    y: u32,
+    x: i32,
+    y: i32,
+}
+
+impl CavePoint {
+    fn offset(self, (dx, dy): (i32, i32)) -> CavePoint {
+        CavePoint {
+            x: self.x + dx,
+            y: self.y + dy,
+        }
+    }
+}
+
+fn parse_paths(input: &str) -> Result<Vec<RockPath>, String> {
+    let mut paths = Vec::new();
+    for (line_idx, line) in input.lines().enumerate() {
+        let line_num = line_idx + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (rest, path) = parse_path(line)
+            .finish()
+            .map_err(|e| format!("line {line_num}: malformed rock path: {e:?}"))?;
+        if !rest.is_empty() {
+            return Err(format!(
+                "line {line_num}: unexpected {rest:?} after rock path"
+            ));
+        }
+        if let Some(p) = path.points.iter().find(|p| p.y < 0) {
+            return Err(format!(
+                "line {line_num}: point {},{} is above the top of the cave",
+                p.x, p.y
+            ));
+        }
+        paths.push(path);
+    }
+
+    if paths.is_empty() {
+        return Err("no rock paths".to_string());
+    }
+    Ok(paths)
 }
 
 fn parse_path(path_desc: &str) -> nom::IResult<&str, RockPath> {
@@ -140,7 +747,7 @@ fn parse_path(path_desc: &str) -> nom::IResult<&str, RockPath> {
 
     let parse_pair = |input| {
         let (input, (x, y)) =
-            sequence::separated_pair(character::complete::u32, tag(","), character::complete::u32)(
+            sequence::separated_pair(character::complete::i32, tag(","), character::complete::i32)(
                 input,
             )?;
         IResult::Ok((input, CavePoint { x, y }))
@@ -150,3 +757,249 @@ fn parse_path(path_desc: &str) -> nom::IResult<&str, RockPath> {
         nom::multi::separated_list1(nom::bytes::complete::tag(" -> "), parse_pair)(path_desc)?;
     Ok((input, RockPath { points }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "498,4 -> 498,6 -> 496,6\n503,4 -> 502,4 -> 502,9 -> 494,9";
+
+    #[test]
+    fn sample_layouts() {
+        let paths = parse_paths(SAMPLE).unwrap();
+        let rules = SandRules::puzzle(None);
+        let mut map = draw_map(&paths, &rules);
+        assert_eq!(
+            map.to_string(),
+            concat!(
+                "......+...\n",
+                "..........\n",
+                "..........\n",
+                "..........\n",
+                "....#...##\n",
+                "....#...#.\n",
+                "..###...#.\n",
+                "........#.\n",
+                "........#.\n",
+                "#########.\n",
+            )
+        );
+
+        let mut sim = Simulation::new(&rules);
+        for _ in 0..5 {
+            sim.step(&mut map);
+        }
+        assert_eq!(
+            map.to_string(),
+            concat!(
+                "......+...\n",
+                "..........\n",
+                "..........\n",
+                "..........\n",
+                "....#...##\n",
+                "....#...#.\n",
+                "..###...#.\n",
+                "......o.#.\n",
+                "....oooo#.\n",
+                "#########.\n",
+            )
+        );
+
+        while sim.step(&mut map).is_some() {}
+        let expected = concat!(
+            "......+...\n",
+            "..........\n",
+            "......o...\n",
+            ".....ooo..\n",
+            "....#ooo##\n",
+            "...o#ooo#.\n",
+            "..###ooo#.\n",
+            "....oooo#.\n",
+            ".o.ooooo#.\n",
+            "#########.\n",
+        );
+        assert_eq!(map.to_string(), expected);
+
+        // Parsing the drawing gives back the same cave.
+        let (min, max) = map.bounding_box().unwrap();
+        let parsed = CaveMap::parse(expected, min).unwrap();
+        assert_eq!(parsed.to_string(), expected);
+        assert_eq!(parsed.sources, vec![SOURCE]);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let pos = CavePoint { x, y };
+                assert_eq!(parsed.get(pos), map.get(pos), "{pos:?}");
+            }
+        }
+        assert!(CaveMap::parse("..\n.\n", min).is_err());
+        assert!(CaveMap::parse("..x\n", min).is_err());
+    }
+
+    #[test]
+    fn diagonal_paths() {
+        let (a, b) = (CavePoint { x: 0, y: 0 }, CavePoint { x: 4, y: 2 });
+        assert_eq!(
+            rasterise(a, b),
+            [(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+                .map(|(x, y)| CavePoint { x, y })
+                .to_vec()
+        );
+        let mut back = rasterise(b, a);
+        back.reverse();
+        assert_eq!(back.len(), 5);
+        assert_eq!(rasterise(a, a), vec![a]);
+
+        let paths = parse_paths("497,2 -> 500,5 -> 503,2\n505,6").unwrap();
+        let map = draw_map(&paths, &SandRules::puzzle(None));
+        assert_eq!(
+            map.to_string(),
+            concat!(
+                "...+.....\n",
+                ".........\n",
+                "#.....#..\n",
+                ".#...#...\n",
+                "..#.#....\n",
+                "...#.....\n",
+                "........#\n",
+            )
+        );
+
+        assert!(parse_paths("498,4 -> 498,6 ->").is_err());
+        assert!(parse_paths("498,4 -> 498,6 x").is_err());
+        assert!(parse_paths("498,-1 -> 498,6").is_err());
+        assert!(parse_paths("\n").is_err());
+    }
+
+    #[test]
+    fn deep_cave() {
+        // A three-wide ledge 3000 rows down. Everything above the floor fills
+        // except the ledge and the one cell sheltered beneath its middle.
+        let paths = parse_paths("499,3000 -> 501,3000").unwrap();
+        let floor_y = floor_y(&paths);
+
+        let p1_rules = SandRules::puzzle(None);
+        assert!(matches!(
+            compute(SOURCE, &mut draw_map(&paths, &p1_rules), &p1_rules),
+            ComputeResult::Flow(1)
+        ));
+        let p2_rules = SandRules::puzzle(Some(floor_y));
+        assert!(matches!(
+            compute(SOURCE, &mut draw_map(&paths, &p2_rules), &p2_rules),
+            ComputeResult::Rest(n) if n as i32 == floor_y * floor_y - 4
+        ));
+    }
+
+    #[test]
+    fn wide_cave() {
+        // A shelf reaching past x = 1000, 300 rows down. Sand piles up on it
+        // in a triangle until it blocks the source, with or without a floor.
+        let paths = parse_paths("0,300 -> 1500,300").unwrap();
+        let floor_y = floor_y(&paths);
+
+        for rules in [SandRules::puzzle(None), SandRules::puzzle(Some(floor_y))] {
+            let mut map = draw_map(&paths, &rules);
+            assert_eq!(map.get(CavePoint { x: 1500, y: 300 }), Some(CaveElem::Rock));
+            assert!(matches!(
+                compute(SOURCE, &mut map, &rules),
+                ComputeResult::Rest(90000)
+            ));
+        }
+    }
+
+    #[test]
+    fn grain_by_grain_matches_compute() {
+        let paths = parse_paths(SAMPLE).unwrap();
+        let floor_y = floor_y(&paths);
+
+        for (floor_y, expected) in [(None, 24), (Some(floor_y), 93)] {
+            let rules = SandRules::puzzle(floor_y);
+            let mut map = draw_map(&paths, &rules);
+            let mut pourer = Pourer::new(SOURCE);
+            let mut grains = 0;
+            let end = loop {
+                match pourer.drop_grain(&mut map, &rules) {
+                    Grain::Rest(pos) => {
+                        assert_eq!(map.get(pos), Some(CaveElem::Sand));
+                        grains += 1;
+                    }
+                    end => break end,
+                }
+            };
+            assert_eq!(grains, expected);
+            assert_eq!(
+                end,
+                if floor_y.is_some() {
+                    Grain::Blocked
+                } else {
+                    Grain::Abyss
+                }
+            );
+
+            let mut compute_map = draw_map(&paths, &rules);
+            compute(SOURCE, &mut compute_map, &rules);
+            assert_eq!(map.map, compute_map.map);
+        }
+    }
+
+    #[test]
+    fn multiple_sources() {
+        let paths = parse_paths(SAMPLE).unwrap();
+        let rules = SandRules {
+            sources: vec![
+                Source {
+                    pos: SOURCE,
+                    budget: Some(5),
+                },
+                Source {
+                    pos: CavePoint { x: 496, y: 0 },
+                    budget: None,
+                },
+            ],
+            moves: vec![(0, 1), (1, 1), (-1, 1)],
+            floor_y: Some(12),
+        };
+        let mut map = draw_map(&paths, &rules);
+        let mut sim = Simulation::new(&rules);
+        while let Some((_, pos)) = sim.step(&mut map) {
+            assert!(pos.y < 12);
+        }
+
+        // The budgeted source stops early, the other fills up to itself.
+        assert_eq!(sim.dropped[0], 5);
+        assert!(!sim.reached_abyss);
+        assert_eq!(map.get(CavePoint { x: 496, y: 0 }), Some(CaveElem::Sand));
+        assert_eq!(map.get(SOURCE), Some(CaveElem::Air));
+        let sand = map.map.iter().filter(|&&e| e == CaveElem::Sand).count();
+        assert_eq!(sand as u32, sim.dropped.iter().sum::<u32>());
+    }
+
+    #[test]
+    fn source_below_rocks() {
+        let paths = parse_paths(SAMPLE).unwrap();
+        let config = Config::from_args(["--source", "500,20"].map(String::from).into_iter());
+        let rules = config.custom_rules().unwrap();
+        let mut map = draw_map(&paths, &rules);
+        assert_eq!(map.get(CavePoint { x: 500, y: 20 }), Some(CaveElem::Air));
+
+        let mut sim = Simulation::new(&rules);
+        while sim.step(&mut map).is_some() {}
+        assert_eq!(sim.dropped, vec![0]);
+        assert!(sim.reached_abyss);
+    }
+
+    #[test]
+    #[should_panic(expected = "--source must not be above the top of the cave")]
+    fn config_rejects_source_above_cave() {
+        Config::from_args(["--source", "500,-1"].map(String::from).into_iter());
+    }
+
+    #[test]
+    #[should_panic(expected = "--source 500,12 is not above the floor at 12")]
+    fn config_rejects_source_in_floor() {
+        Config::from_args(
+            ["--source", "500,12", "--floor", "12"]
+                .map(String::from)
+                .into_iter(),
+        );
+    }
+}
@@ -14,6 +14,8 @@
 
 use std::collections::HashSet;
 
+use aoc2022::Args;
+
 fn main() {
     use regex::Regex;
     let re = Regex::new(
@@ -39,35 +41,247 @@ fn main() {
         })
         .collect();
 
-    println!(
-        "y=10: {}\ny=2000000: {}",
-        part1(&pings, 10),
-        part1(&pings, 2000000)
-    );
+    let config = Config::from_args(std::env::args().skip(1), &pings);
 
+    println!("y={}: {}", config.row, part1(&pings, config.row));
+
+    let hole = part2(&pings, config.max_coord);
+    if let Some(pos) = hole {
+        println!("({}, {})", pos.x, pos.y);
+    }
     println!(
-        "search space 20: {:?}\nsearch space 4000000: {:?}",
-        part2(&pings, 20),
-        part2(&pings, 4000000)
+        "search space {}: {:?}",
+        config.max_coord,
+        hole.map(|pos| pos.tuning_frequency(config.tuning_multiplier))
     );
+
+    // Cross-check the perimeter search against the (slow) row scan.
+    if config.verify {
+        assert_eq!(
+            part2_perimeter(&pings, config.max_coord),
+            part2_scan(&pings, config.max_coord)
+        );
+        println!("verified");
+    }
+
+    if config.area {
+        let rect = config.rect();
+        let uncovered = uncovered_cells(&pings, rect);
+        println!(
+            "covered in {rect:?}: {}\nuncovered: {}",
+            covered_area(&pings, rect),
+            uncovered.len()
+        );
+        if uncovered.len() <= 20 {
+            for pos in uncovered {
+                println!("  ({}, {})", pos.x, pos.y);
+            }
+        }
+    }
+
+    if let Some(path) = &config.render {
+        let rect = config.rect();
+        let num_cells = (rect.width() as i64) * (rect.height() as i64);
+        assert!(num_cells <= 4000000, "{rect:?} is too large to render");
+        if path == "-" {
+            print!("{}", render_ascii(&pings, rect));
+        } else {
+            let file = std::fs::File::create(path).unwrap();
+            write_ppm(&pings, rect, std::io::BufWriter::new(file)).unwrap();
+        }
+    }
+}
+
+// Command line options. The puzzle's example and real input use different
+// rows and search spaces; unless given explicitly these are picked based on
+// the magnitude of the input coordinates.
+//
+//   --row N           row to count excluded positions in for part 1
+//   --max-coord N     search x and y in 0..=N for part 2
+//   --multiplier N    tuning frequency is x * N + y
+//   --verify          cross-check part 2 against the row scan
+//   --area            count covered and uncovered cells in the rectangle
+//   --render PATH     draw coverage of the rectangle as a PPM image, or as
+//                     text on stdout if PATH is "-"
+//   --rect X0,Y0,X1,Y1
+//                     rectangle for --area and --render; defaults to the
+//                     part 2 search space
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Config {
+    row: i32,
+    max_coord: i32,
+    tuning_multiplier: i64,
+    verify: bool,
+    area: bool,
+    render: Option<String>,
+    rect: Option<Rect>,
+}
+
+impl Config {
+    fn from_args(args: impl Iterator<Item = String>, pings: &[Ping]) -> Config {
+        let is_sample = pings
+            .iter()
+            .all(|p| p.sensor.x.abs() <= 1000 && p.sensor.y.abs() <= 1000);
+        let mut config = if is_sample {
+            Config {
+                row: 10,
+                max_coord: 20,
+                tuning_multiplier: 4000000,
+                verify: false,
+                area: false,
+                render: None,
+                rect: None,
+            }
+        } else {
+            Config {
+                row: 2000000,
+                max_coord: 4000000,
+                tuning_multiplier: 4000000,
+                verify: false,
+                area: false,
+                render: None,
+                rect: None,
+            }
+        };
+
+        let mut args = Args::new(args);
+        while let Some(flag) = args.next_flag() {
+            match flag {
+                "--row" => config.row = args.value(),
+                "--max-coord" => config.max_coord = args.value(),
+                "--multiplier" => config.tuning_multiplier = args.value(),
+                "--verify" => config.verify = true,
+                "--area" => config.area = true,
+                "--render" => config.render = Some(args.string()),
+                "--rect" => {
+                    let v = args.string();
+                    let coords: Vec<i32> = v.split(',').map(|c| args.parse(c)).collect();
+                    let [x0, y0, x1, y1] = coords[..] else {
+                        panic!("--rect needs four coordinates, got {v:?}");
+                    };
+                    config.rect = Some(Rect {
+                        min: Pos { x: x0, y: y0 },
+                        max: Pos { x: x1, y: y1 },
+                    });
+                }
+                _ => args.unknown(),
+            }
+        }
+
+        config
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect.unwrap_or(Rect {
+            min: Pos { x: 0, y: 0 },
+            max: Pos {
+                x: self.max_coord,
+                y: self.max_coord,
+            },
+        })
+    }
 }
 
 fn part1(pings: &[Ping], y: i32) -> i32 {
-    scan_row(pings, y).0
+    scan_row(pings, y, i32::MAX).0
+}
+
+fn part2(pings: &[Ping], max_coord: i32) -> Option<Pos> {
+    part2_perimeter(pings, max_coord).or_else(|| part2_scan(pings, max_coord))
+}
+
+// If there is exactly one uncovered point in the search space, it must lie
+// just outside the diamonds of several sensors. In rotated coordinates
+// (u = x + y, v = x - y) each diamond's edges are axis-aligned, so the point
+// sits where a u-line one step past some sensor's range meets such a v-line,
+// or where one of those lines meets the search space boundary. Like
+// `part2_scan`, gives up if more than one point is uncovered.
+fn part2_perimeter(pings: &[Ping], max_coord: i32) -> Option<Pos> {
+    let mut us = Vec::new();
+    let mut vs = Vec::new();
+    for p in pings {
+        let r = p.radius() + 1;
+        let u = p.sensor.x + p.sensor.y;
+        let v = p.sensor.x - p.sensor.y;
+        us.extend([u - r, u + r]);
+        vs.extend([v - r, v + r]);
+    }
+    us.sort_unstable();
+    us.dedup();
+    vs.sort_unstable();
+    vs.dedup();
+
+    let mut candidates = Vec::new();
+    for &u in &us {
+        for &v in &vs {
+            if (u + v) % 2 == 0 {
+                candidates.push(Pos {
+                    x: (u + v) / 2,
+                    y: (u - v) / 2,
+                });
+            }
+        }
+    }
+    for edge in [0, max_coord] {
+        candidates.push(Pos { x: edge, y: 0 });
+        candidates.push(Pos {
+            x: edge,
+            y: max_coord,
+        });
+        for &u in &us {
+            candidates.push(Pos {
+                x: edge,
+                y: u - edge,
+            });
+            candidates.push(Pos {
+                x: u - edge,
+                y: edge,
+            });
+        }
+        for &v in &vs {
+            candidates.push(Pos {
+                x: edge,
+                y: edge - v,
+            });
+            candidates.push(Pos {
+                x: v + edge,
+                y: edge,
+            });
+        }
+    }
+
+    let mut holes: HashSet<Pos> = candidates
+        .into_iter()
+        .filter(|c| {
+            (0..=max_coord).contains(&c.x)
+                && (0..=max_coord).contains(&c.y)
+                && pings.iter().all(|p| !p.covers(*c))
+        })
+        .collect();
+    match holes.len() {
+        1 => holes.drain().next(),
+        _ => None,
+    }
 }
 
-fn part2(pings: &[Ping], max_coord: i32) -> Option<i64> {
+// The only uncovered position in the search space, if there is exactly one.
+fn part2_scan(pings: &[Ping], max_coord: i32) -> Option<Pos> {
+    let mut hole = None;
     for y in 0..=max_coord {
-        if let Some(x) = scan_row(pings, y).1 {
-            println!("({x}, {y})");
-            return Some(y as i64 + (x as i64 * 4000000));
+        for x in scan_row(pings, y, max_coord).1 {
+            if hole.is_some() {
+                return None;
+            }
+            hole = Some(Pos { x, y });
         }
     }
 
-    None
+    hole
 }
 
-fn scan_row(pings: &[Ping], y: i32) -> (i32, Option<i32>) {
+// Returns the number of positions in row `y` that cannot contain a beacon,
+// and the x coordinates of the first two positions in `0..=max_x` that could.
+fn scan_row(pings: &[Ping], y: i32, max_x: i32) -> (i32, Vec<i32>) {
     let beacons: HashSet<i32> = pings
         .iter()
         .filter(|p| p.beacon.y == y)
@@ -77,26 +291,32 @@ fn scan_row(pings: &[Ping], y: i32) -> (i32, Option<i32>) {
     zones.sort_unstable();
     zones.reverse();
 
+    // Records uncovered positions in `from..=to` within the search bounds,
+    // stopping once it's clear there is more than one.
+    let mut holes = Vec::new();
+    let mut note_gap = |from: i32, to: i32| {
+        for x in std::cmp::max(from, 0)..=std::cmp::min(to, max_x) {
+            if holes.len() > 1 {
+                break;
+            }
+            if !beacons.contains(&x) {
+                holes.push(x);
+            }
+        }
+    };
+
     let mut total = 0;
-    let mut one_hole = false;
-    let mut hole = None;
     let mut cur_zone = match zones.pop() {
         Some(z) => z,
-        None => return (0, None),
+        None => {
+            note_gap(0, max_x);
+            return (0, holes);
+        }
     };
+    note_gap(0, cur_zone.0 - 1);
     while let Some(z) = zones.pop() {
-        if cur_zone.1 < z.0 {
-            if cur_zone.1 + 2 == z.0
-                && cur_zone.1 + 1 <= 4000000
-                && !beacons.contains(&(cur_zone.1 + 1))
-            {
-                if hole == None {
-                    one_hole = true;
-                    hole = Some(cur_zone.1 + 1);
-                } else {
-                    one_hole = false;
-                }
-            }
+        if cur_zone.1 + 1 < z.0 {
+            note_gap(cur_zone.1 + 1, z.0 - 1);
             total += cur_zone.1 - cur_zone.0 + 1;
             cur_zone = z;
             continue;
@@ -105,9 +325,124 @@ fn scan_row(pings: &[Ping], y: i32) -> (i32, Option<i32>) {
         cur_zone.0 = std::cmp::min(cur_zone.0, z.0);
         cur_zone.1 = std::cmp::max(cur_zone.1, z.1);
     }
+    note_gap(cur_zone.1.saturating_add(1), max_x);
 
     total += cur_zone.1 - cur_zone.0 + 1;
-    (total, if one_hole { hole } else { None })
+    (total, holes)
+}
+
+// Spans of row `y` inside `rect` covered by at least one sensor, including
+// the cells of known beacons. Spans are sorted and don't touch or overlap.
+fn row_coverage(pings: &[Ping], y: i32, rect: Rect) -> Vec<(i32, i32)> {
+    let mut zones: Vec<(i32, i32)> = pings
+        .iter()
+        .flat_map(|p| p.coverage(y))
+        .map(|(lo, hi)| (lo.max(rect.min.x), hi.min(rect.max.x)))
+        .filter(|(lo, hi)| lo <= hi)
+        .collect();
+    zones.sort_unstable();
+
+    let mut merged: Vec<(i32, i32)> = Vec::new();
+    for z in zones {
+        match merged.last_mut() {
+            Some(last) if last.1.saturating_add(1) >= z.0 => last.1 = last.1.max(z.1),
+            _ => merged.push(z),
+        }
+    }
+    merged
+}
+
+// Number of cells in `rect` within range of some sensor.
+fn covered_area(pings: &[Ping], rect: Rect) -> i64 {
+    (rect.min.y..=rect.max.y)
+        .map(|y| {
+            row_coverage(pings, y, rect)
+                .into_iter()
+                .map(|(lo, hi)| (hi - lo + 1) as i64)
+                .sum::<i64>()
+        })
+        .sum()
+}
+
+// Every cell in `rect` out of range of all sensors, in row-major order.
+fn uncovered_cells(pings: &[Ping], rect: Rect) -> Vec<Pos> {
+    let mut cells = Vec::new();
+    for y in rect.min.y..=rect.max.y {
+        let mut x = rect.min.x;
+        for (lo, hi) in row_coverage(pings, y, rect) {
+            cells.extend((x..lo).map(|x| Pos { x, y }));
+            x = hi + 1;
+        }
+        if x <= rect.max.x {
+            cells.extend((x..=rect.max.x).map(|x| Pos { x, y }));
+        }
+    }
+    cells
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Cell {
+    Sensor,
+    Beacon,
+    Covered,
+    Uncovered,
+}
+
+// Classifies every cell of `rect`, row by row.
+fn coverage_map(pings: &[Ping], rect: Rect) -> Vec<Vec<Cell>> {
+    let sensors: HashSet<Pos> = pings.iter().map(|p| p.sensor).collect();
+    let beacons: HashSet<Pos> = pings.iter().map(|p| p.beacon).collect();
+    (rect.min.y..=rect.max.y)
+        .map(|y| {
+            let spans = row_coverage(pings, y, rect);
+            (rect.min.x..=rect.max.x)
+                .map(|x| {
+                    let pos = Pos { x, y };
+                    if sensors.contains(&pos) {
+                        Cell::Sensor
+                    } else if beacons.contains(&pos) {
+                        Cell::Beacon
+                    } else if spans.iter().any(|&(lo, hi)| lo <= x && x <= hi) {
+                        Cell::Covered
+                    } else {
+                        Cell::Uncovered
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Draws `rect` in the puzzle's notation.
+fn render_ascii(pings: &[Ping], rect: Rect) -> String {
+    let mut out = String::new();
+    for row in coverage_map(pings, rect) {
+        out.extend(row.into_iter().map(|c| match c {
+            Cell::Sensor => 'S',
+            Cell::Beacon => 'B',
+            Cell::Covered => '#',
+            Cell::Uncovered => '.',
+        }));
+        out.push('\n');
+    }
+    out
+}
+
+// Writes `rect` as a binary PPM image, one pixel per cell.
+fn write_ppm(pings: &[Ping], rect: Rect, mut out: impl std::io::Write) -> std::io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", rect.width(), rect.height())?;
+    for row in coverage_map(pings, rect) {
+        for c in row {
+            let rgb: [u8; 3] = match c {
+                Cell::Sensor => [220, 40, 40],
+                Cell::Beacon => [40, 80, 220],
+                Cell::Covered => [90, 90, 90],
+                Cell::Uncovered => [255, 255, 255],
+            };
+            out.write_all(&rgb)?;
+        }
+    }
+    out.flush()
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -117,18 +452,32 @@ struct Ping {
 }
 
 impl Ping {
-    fn exclusion_zone(&self, y: i32) -> Option<(i32, i32)> {
-        let dist = (self.beacon.x - self.sensor.x).abs() + (self.beacon.y - self.sensor.y).abs();
+    fn radius(&self) -> i32 {
+        self.sensor.distance(self.beacon)
+    }
+
+    // Whether `pos` is within range of the sensor, including the beacon itself.
+    fn covers(&self, pos: Pos) -> bool {
+        self.sensor.distance(pos) <= self.radius()
+    }
+
+    // Span of row `y` within range of the sensor.
+    fn coverage(&self, y: i32) -> Option<(i32, i32)> {
+        let dist = self.radius();
         if y > self.sensor.y + dist || y < self.sensor.y - dist {
             return None;
         }
 
         let zone_side_width = dist - (self.sensor.y - y).abs();
         assert!(zone_side_width >= 0, "{zone_side_width}");
-        let mut zone = (
+        Some((
             self.sensor.x - zone_side_width,
             self.sensor.x + zone_side_width,
-        );
+        ))
+    }
+
+    fn exclusion_zone(&self, y: i32) -> Option<(i32, i32)> {
+        let mut zone = self.coverage(y)?;
         assert!(zone.0 <= zone.1, "{} > {}", zone.0, zone.1);
 
         if self.beacon.y != y {
@@ -152,12 +501,39 @@ impl Ping {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 struct Pos {
     x: i32,
     y: i32,
 }
 
+impl Pos {
+    fn distance(self, other: Pos) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    fn tuning_frequency(self, multiplier: i64) -> i64 {
+        self.x as i64 * multiplier + self.y as i64
+    }
+}
+
+// Rectangle of cells, inclusive of both corners.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Rect {
+    min: Pos,
+    max: Pos,
+}
+
+impl Rect {
+    fn width(&self) -> i32 {
+        self.max.x - self.min.x + 1
+    }
+
+    fn height(&self) -> i32 {
+        self.max.y - self.min.y + 1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,4 +552,120 @@ mod tests {
         assert_eq!(ping.exclusion_zone(8), Some((0, 16)));
         assert_eq!(ping.exclusion_zone(10), Some((3, 14)));
     }
+
+    fn sample_pings() -> Vec<Ping> {
+        [
+            (2, 18, -2, 15),
+            (9, 16, 10, 16),
+            (13, 2, 15, 3),
+            (12, 14, 10, 16),
+            (10, 20, 10, 16),
+            (14, 17, 10, 16),
+            (8, 7, 2, 10),
+            (2, 0, 2, 10),
+            (0, 11, 2, 10),
+            (20, 14, 25, 17),
+            (17, 20, 21, 22),
+            (16, 7, 15, 3),
+            (14, 3, 15, 3),
+            (20, 1, 15, 3),
+        ]
+        .into_iter()
+        .map(|(s_x, s_y, b_x, b_y)| Ping {
+            sensor: Pos { x: s_x, y: s_y },
+            beacon: Pos { x: b_x, y: b_y },
+        })
+        .collect()
+    }
+
+    #[test]
+    fn part2_perimeter_matches_scan() {
+        let pings = sample_pings();
+        assert_eq!(part2_perimeter(&pings, 20), Some(Pos { x: 14, y: 11 }));
+        assert_eq!(part2_perimeter(&pings, 20), part2_scan(&pings, 20));
+
+        // A larger square leaves lots of room for the beacon.
+        assert_eq!(uncovered_cells(&pings, square(30)).len(), 352);
+        assert_eq!(part2_perimeter(&pings, 30), None);
+        assert_eq!(part2_scan(&pings, 30), None);
+        assert_eq!(part2(&pings, 30), None);
+    }
+
+    fn square(max_coord: i32) -> Rect {
+        Rect {
+            min: Pos { x: 0, y: 0 },
+            max: Pos {
+                x: max_coord,
+                y: max_coord,
+            },
+        }
+    }
+
+    #[test]
+    fn sample_coverage() {
+        let pings = sample_pings();
+        let search = Rect {
+            min: Pos { x: 0, y: 0 },
+            max: Pos { x: 20, y: 20 },
+        };
+        assert_eq!(covered_area(&pings, search), 21 * 21 - 1);
+        assert_eq!(uncovered_cells(&pings, search), vec![Pos { x: 14, y: 11 }]);
+
+        // Row 10 has one beacon in range, which part 1 doesn't count.
+        let row = Rect {
+            min: Pos { x: -100, y: 10 },
+            max: Pos { x: 100, y: 10 },
+        };
+        assert_eq!(covered_area(&pings, row), part1(&pings, 10) as i64 + 1);
+    }
+
+    #[test]
+    fn scan_row_holes() {
+        let ping = |s_x, b_x| Ping {
+            sensor: Pos { x: s_x, y: 0 },
+            beacon: Pos { x: b_x, y: 0 },
+        };
+
+        // Only x=0 is left of the zone.
+        assert_eq!(scan_row(&[ping(6, 11)], 0, 10), (10, vec![0]));
+        // Only x=10 is right of the zone.
+        assert_eq!(scan_row(&[ping(4, -1)], 0, 10), (10, vec![10]));
+        // Both ends are open; the beacon at x=1 doesn't count as a hole.
+        assert_eq!(scan_row(&[ping(5, 1)], 0, 10), (8, vec![0, 10]));
+        // Two holes between zones.
+        assert_eq!(scan_row(&[ping(2, 4), ping(9, 11)], 0, 10), (8, vec![5, 6]));
+        assert_eq!(scan_row(&[], 0, 10), (0, vec![0, 1]));
+    }
+
+    #[test]
+    fn config_defaults() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let sample = Config::from_args(args(&[]).into_iter(), &sample_pings());
+        assert_eq!(
+            sample,
+            Config {
+                row: 10,
+                max_coord: 20,
+                tuning_multiplier: 4000000,
+                verify: false,
+                area: false,
+                render: None,
+                rect: None,
+            }
+        );
+
+        let mut pings = sample_pings();
+        pings[0].sensor.x = 3000000;
+        let real = Config::from_args(args(&["--row", "5", "--verify"]).into_iter(), &pings);
+        assert_eq!(real.row, 5);
+        assert_eq!(real.max_coord, 4000000);
+        assert!(real.verify);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad value for --max-coord")]
+    fn config_rejects_out_of_range() {
+        let args = ["--max-coord", "5000000000"].map(String::from);
+        Config::from_args(args.into_iter(), &sample_pings());
+    }
 }
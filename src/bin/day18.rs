@@ -12,83 +12,504 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::VecDeque;
+use std::io::Read;
 
+use bitvec::{bitvec, vec::BitVec};
 use nalgebra::{vector, Point3, Vector3};
 
+use aoc2022::Args;
+
 fn main() {
-    let drops = std::io::stdin()
-        .lines()
-        .map(|l| {
-            let l = l.unwrap();
-            let mut iter = l.split(',').map(|n| n.parse::<isize>().unwrap());
-            let x = iter.next().unwrap();
-            let y = iter.next().unwrap();
-            let z = iter.next().unwrap();
-            Point3::new(x, y, z)
-        })
-        .collect::<Vec<_>>();
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let drops = parse_drops(&input).unwrap_or_else(|e| panic!("{e}"));
 
     let mut grid = Grid::new();
     for drop in drops.iter() {
         grid.set(drop);
     }
 
-    let mut surface_area = 0;
+    let faces = exposed_faces(&grid, &drops);
+    let surface_area = faces.len();
+    println!("{surface_area}");
+
+    let exterior = exterior_air(&grid, &drops);
+    let outer_faces: Vec<Face> = faces
+        .iter()
+        .copied()
+        .filter(|f| exterior.get(&f.neighbour()))
+        .collect();
+    let outer_surface_area = outer_faces.len();
+    println!("{outer_surface_area}");
+
+    let config = Config::from_args(std::env::args().skip(1));
+    if config.pockets {
+        let lava = components(|p| grid.get(p));
+        println!(
+            "droplet: {} cubes in {} connected components",
+            lava.iter().map(Vec::len).sum::<usize>(),
+            lava.len()
+        );
+
+        let pockets = air_pockets(&grid, &exterior);
+        let pocket_surface = pockets.iter().map(|p| p.surface_area).sum::<usize>();
+        println!(
+            "{} air pockets, {} cubes of air, {pocket_surface} faces",
+            pockets.len(),
+            pockets.iter().map(|p| p.cells.len()).sum::<usize>()
+        );
+        for (i, pocket) in pockets.iter().enumerate() {
+            println!(
+                "  pocket {i}: volume {}, bounds {:?} to {:?}, surface area {}",
+                pocket.cells.len(),
+                pocket.min.coords.as_slice(),
+                pocket.max.coords.as_slice(),
+                pocket.surface_area
+            );
+        }
+        assert_eq!(pocket_surface, surface_area - outer_surface_area);
+    }
+
+    if let Some((format, path)) = &config.mesh {
+        let faces = if config.exterior_only {
+            &outer_faces
+        } else {
+            &faces
+        };
+        let quads = if config.merge {
+            merge_faces(faces)
+        } else {
+            faces.iter().map(Face::quad).collect()
+        };
+        let file = std::fs::File::create(path).unwrap();
+        let out = std::io::BufWriter::new(file);
+        match format {
+            MeshFormat::Obj => write_obj(&quads, out),
+            MeshFormat::StlAscii => write_stl_ascii(&quads, out),
+            MeshFormat::StlBinary => write_stl_binary(&quads, out),
+        }
+        .unwrap();
+        let area = quads.iter().map(Quad::area).sum::<isize>();
+        println!("wrote {} quads covering {area} faces", quads.len());
+    }
+}
+
+// Duplicate cubes are dropped with a warning, since counting them twice would
+// throw off the surface area.
+fn parse_drops(input: &str) -> Result<Vec<Point3<isize>>, String> {
+    use std::collections::HashMap;
+
+    let mut drops = Vec::new();
+    let mut first_seen = HashMap::<Point3<isize>, usize>::new();
+    for (line_idx, line) in input.lines().enumerate() {
+        let line_num = line_idx + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let coords = line
+            .split(',')
+            .map(|n| n.trim().parse::<isize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("line {line_num}: {line:?}: {e}"))?;
+        let [x, y, z] = coords[..] else {
+            return Err(format!(
+                "line {line_num}: {line:?}: expected 3 coordinates, found {}",
+                coords.len()
+            ));
+        };
+        let drop = Point3::new(x, y, z);
+        if !Grid::in_range(&drop) {
+            return Err(format!(
+                "line {line_num}: {line:?}: coordinates must be in 0..{MAX_DIM}"
+            ));
+        }
+
+        if let Some(first) = first_seen.get(&drop) {
+            eprintln!("warning: line {line_num}: {line:?} duplicates line {first}");
+            continue;
+        }
+        first_seen.insert(drop, line_num);
+        drops.push(drop);
+    }
+    Ok(drops)
+}
+
+// Command line options:
+//
+//   --obj PATH         write the exposed faces as a Wavefront OBJ mesh
+//   --stl PATH         write them as an ASCII STL mesh
+//   --stl-binary PATH  write them as a binary STL mesh
+//   --exterior         only include faces on the outside of the droplet
+//   --merge            merge adjacent coplanar faces into larger quads
+//   --pockets          list the air pockets trapped inside the droplet
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Config {
+    pockets: bool,
+    mesh: Option<(MeshFormat, std::path::PathBuf)>,
+    exterior_only: bool,
+    merge: bool,
+}
+
+impl Config {
+    fn from_args(args: impl Iterator<Item = String>) -> Config {
+        let mut config = Config {
+            pockets: false,
+            mesh: None,
+            exterior_only: false,
+            merge: false,
+        };
+
+        let mut args = Args::new(args);
+        while let Some(flag) = args.next_flag() {
+            match flag {
+                "--obj" => config.mesh = Some((MeshFormat::Obj, args.string().into())),
+                "--stl" => config.mesh = Some((MeshFormat::StlAscii, args.string().into())),
+                "--stl-binary" => config.mesh = Some((MeshFormat::StlBinary, args.string().into())),
+                "--exterior" => config.exterior_only = true,
+                "--merge" => config.merge = true,
+                "--pockets" => config.pockets = true,
+                _ => args.unknown(),
+            }
+        }
+
+        config
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Face {
+    cube: Point3<isize>,
+    // One of `ADJACENT_OFFSETS`.
+    normal: Vector3<isize>,
+}
+
+impl Face {
+    fn neighbour(&self) -> Point3<isize> {
+        self.cube + self.normal
+    }
+
+    fn orientation(&self) -> (usize, bool) {
+        let axis = self.normal.iamax();
+        (axis, self.normal[axis] > 0)
+    }
+
+    fn quad(&self) -> Quad {
+        let (axis, positive) = self.orientation();
+        let u = self.cube[(axis + 1) % 3];
+        let v = self.cube[(axis + 2) % 3];
+        let plane = self.cube[axis] + positive as isize;
+        Quad::new(axis, positive, plane, (u, v), (u + 1, v + 1))
+    }
+}
+
+fn exposed_faces(grid: &Grid, drops: &[Point3<isize>]) -> Vec<Face> {
+    let mut faces = Vec::new();
     for drop in drops.iter() {
         for offset in ADJACENT_OFFSETS {
             if !grid.get(&(drop + offset)) {
-                surface_area += 1;
+                faces.push(Face {
+                    cube: *drop,
+                    normal: offset,
+                });
             }
         }
     }
+    faces
+}
 
-    println!("{surface_area}");
+struct Exterior {
+    // The bounding box is padded by one cell so air can flow all the way
+    // around the droplet.
+    min: Point3<isize>,
+    dims: Vector3<usize>,
+    cells: BitVec,
+}
 
-    let mut enqueued = Grid::new();
-    let mut visited = Grid::new();
-    let mut cube_queue = VecDeque::<Point3<isize>>::new();
-
-    // Enqueue all the outer surface cubes.
-    for fixed_coord in 0..2 {
-        for extreme in [0, MAX_DIM as isize - 2] {
-            for u in 0..MAX_DIM as isize {
-                for v in 0..MAX_DIM as isize {
-                    let mut coords = [extreme, u, v];
-                    coords.rotate_left(fixed_coord);
-                    let p = Point3::from_slice(&coords);
-                    enqueued.set(&p);
-                    cube_queue.push_back(p);
-                }
+impl Exterior {
+    fn index(&self, p: &Point3<isize>) -> Option<usize> {
+        let offset = p - self.min;
+        let mut index = 0;
+        for axis in 0..3 {
+            let c = usize::try_from(offset[axis]).ok()?;
+            if c >= self.dims[axis] {
+                return None;
             }
+            index = index * self.dims[axis] + c;
         }
+        Some(index)
     }
 
-    let mut outer_surface_area = 0;
-    while let Some(cur) = cube_queue.pop_front() {
-        if visited.get(&cur) {
-            continue;
-        }
-        visited.set(&cur);
+    fn get(&self, p: &Point3<isize>) -> bool {
+        self.index(p).is_none_or(|i| self.cells[i])
+    }
+}
+
+// Starts from a corner of the padded bounding box, which can't be inside the
+// droplet.
+fn exterior_air(grid: &Grid, drops: &[Point3<isize>]) -> Exterior {
+    let Some(first) = drops.first() else {
+        return Exterior {
+            min: Point3::origin(),
+            dims: Vector3::zeros(),
+            cells: BitVec::new(),
+        };
+    };
+    let min = drops.iter().fold(*first, |m, d| m.inf(d)) - Vector3::repeat(1);
+    let max = drops.iter().fold(*first, |m, d| m.sup(d)) + Vector3::repeat(1);
+    let dims = (max - min).map(|c| c as usize + 1);
 
+    let mut exterior = Exterior {
+        min,
+        dims,
+        cells: bitvec![0; dims.product()],
+    };
+    let mut stack = vec![min];
+    exterior.cells.set(0, true);
+    while let Some(cur) = stack.pop() {
         for offset in ADJACENT_OFFSETS {
             let adj = cur + offset;
-            if !Grid::in_range(&adj) {
+            let Some(i) = exterior.index(&adj) else {
                 continue;
-            }
-            if grid.get(&adj) {
-                outer_surface_area += 1;
+            };
+            if exterior.cells[i] || grid.get(&adj) {
                 continue;
             }
-            if enqueued.get(&adj) {
-                continue;
+            exterior.cells.set(i, true);
+            stack.push(adj);
+        }
+    }
+
+    exterior
+}
+
+fn components(member: impl Fn(&Point3<isize>) -> bool) -> Vec<Vec<Point3<isize>>> {
+    let mut seen = Grid::new();
+    let mut result = Vec::new();
+    for x in 0..MAX_DIM as isize {
+        for y in 0..MAX_DIM as isize {
+            for z in 0..MAX_DIM as isize {
+                let p = Point3::new(x, y, z);
+                if seen.get(&p) || !member(&p) {
+                    continue;
+                }
+
+                let mut component = Vec::new();
+                let mut stack = vec![p];
+                seen.set(&p);
+                while let Some(cur) = stack.pop() {
+                    component.push(cur);
+                    for offset in ADJACENT_OFFSETS {
+                        let adj = cur + offset;
+                        if Grid::in_range(&adj) && !seen.get(&adj) && member(&adj) {
+                            seen.set(&adj);
+                            stack.push(adj);
+                        }
+                    }
+                }
+                result.push(component);
             }
-            enqueued.set(&adj);
-            cube_queue.push_back(adj);
         }
     }
+    result
+}
 
-    println!("{outer_surface_area}");
+#[derive(Clone, Debug)]
+struct Pocket {
+    cells: Vec<Point3<isize>>,
+    min: Point3<isize>,
+    max: Point3<isize>,
+    surface_area: usize,
+}
+
+fn air_pockets(grid: &Grid, exterior: &Exterior) -> Vec<Pocket> {
+    components(|p| !grid.get(p) && !exterior.get(p))
+        .into_iter()
+        .map(|cells| {
+            let min = cells.iter().fold(cells[0], |m, c| m.inf(c));
+            let max = cells.iter().fold(cells[0], |m, c| m.sup(c));
+            let surface_area = cells
+                .iter()
+                .flat_map(|c| ADJACENT_OFFSETS.map(|offset| c + offset))
+                .filter(|adj| grid.get(adj))
+                .count();
+            Pocket {
+                cells,
+                min,
+                max,
+                surface_area,
+            }
+        })
+        .collect()
+}
+
+// Corners are counter-clockwise seen from outside.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Quad {
+    corners: [Point3<isize>; 4],
+    normal: Vector3<isize>,
+}
+
+impl Quad {
+    // `min` and `max` are along the two axes after `axis`, in cyclic order.
+    fn new(
+        axis: usize,
+        positive: bool,
+        plane: isize,
+        min: (isize, isize),
+        max: (isize, isize),
+    ) -> Quad {
+        let point = |u, v| {
+            let mut p = Point3::origin();
+            p[axis] = plane;
+            p[(axis + 1) % 3] = u;
+            p[(axis + 2) % 3] = v;
+            p
+        };
+        let mut corners = [
+            point(min.0, min.1),
+            point(max.0, min.1),
+            point(max.0, max.1),
+            point(min.0, max.1),
+        ];
+        let mut normal = Vector3::zeros();
+        normal[axis] = if positive { 1 } else { -1 };
+        if !positive {
+            corners.reverse();
+        }
+        Quad { corners, normal }
+    }
+
+    fn area(&self) -> isize {
+        let a = self.corners[1] - self.corners[0];
+        let b = self.corners[3] - self.corners[0];
+        a.cross(&b).abs().sum()
+    }
+
+    fn triangles(&self) -> [[Point3<isize>; 3]; 2] {
+        let c = self.corners;
+        [[c[0], c[1], c[2]], [c[0], c[2], c[3]]]
+    }
+}
+
+fn merge_faces(faces: &[Face]) -> Vec<Quad> {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    // Group the faces by plane, as cells in that plane's (u, v) coordinates.
+    let mut planes = BTreeMap::<(usize, bool, isize), BTreeSet<(isize, isize)>>::new();
+    for face in faces {
+        let (axis, positive) = face.orientation();
+        let plane = face.cube[axis] + positive as isize;
+        let cell = (face.cube[(axis + 2) % 3], face.cube[(axis + 1) % 3]);
+        planes
+            .entry((axis, positive, plane))
+            .or_default()
+            .insert(cell);
+    }
+
+    let mut quads = Vec::new();
+    for ((axis, positive, plane), mut cells) in planes {
+        // Cells are ordered by v then u, so the first remaining cell is the
+        // bottom-left corner of a new rectangle. Grow it along u as far as
+        // possible, then along v while whole rows are available.
+        while let Some(&(v0, u0)) = cells.iter().next() {
+            let mut u1 = u0 + 1;
+            while cells.contains(&(v0, u1)) {
+                u1 += 1;
+            }
+            let mut v1 = v0 + 1;
+            while (u0..u1).all(|u| cells.contains(&(v1, u))) {
+                v1 += 1;
+            }
+            for v in v0..v1 {
+                for u in u0..u1 {
+                    cells.remove(&(v, u));
+                }
+            }
+            quads.push(Quad::new(axis, positive, plane, (u0, v0), (u1, v1)));
+        }
+    }
+    quads
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum MeshFormat {
+    Obj,
+    StlAscii,
+    StlBinary,
+}
+
+fn write_obj(quads: &[Quad], mut out: impl std::io::Write) -> std::io::Result<()> {
+    use std::collections::HashMap;
+
+    // Share vertices and normals between quads.
+    let mut vertices = HashMap::<Point3<isize>, usize>::new();
+    let mut normals = HashMap::<Vector3<isize>, usize>::new();
+    let mut faces = Vec::new();
+    for quad in quads {
+        let n = match normals.get(&quad.normal) {
+            Some(&n) => n,
+            None => {
+                let v = quad.normal;
+                writeln!(out, "vn {} {} {}", v.x, v.y, v.z)?;
+                let n = normals.len() + 1;
+                normals.insert(v, n);
+                n
+            }
+        };
+        let mut indices = [0; 4];
+        for (i, corner) in quad.corners.iter().enumerate() {
+            indices[i] = match vertices.get(corner) {
+                Some(&v) => v,
+                None => {
+                    writeln!(out, "v {} {} {}", corner.x, corner.y, corner.z)?;
+                    let v = vertices.len() + 1;
+                    vertices.insert(*corner, v);
+                    v
+                }
+            };
+        }
+        faces.push((indices, n));
+    }
+    for ([a, b, c, d], n) in faces {
+        writeln!(out, "f {a}//{n} {b}//{n} {c}//{n} {d}//{n}")?;
+    }
+    out.flush()
+}
+
+fn write_stl_ascii(quads: &[Quad], mut out: impl std::io::Write) -> std::io::Result<()> {
+    writeln!(out, "solid droplet")?;
+    for quad in quads {
+        let n = quad.normal;
+        for triangle in quad.triangles() {
+            writeln!(out, "  facet normal {} {} {}", n.x, n.y, n.z)?;
+            writeln!(out, "    outer loop")?;
+            for p in triangle {
+                writeln!(out, "      vertex {} {} {}", p.x, p.y, p.z)?;
+            }
+            writeln!(out, "    endloop")?;
+            writeln!(out, "  endfacet")?;
+        }
+    }
+    writeln!(out, "endsolid droplet")?;
+    out.flush()
+}
+
+fn write_stl_binary(quads: &[Quad], mut out: impl std::io::Write) -> std::io::Result<()> {
+    out.write_all(&[0; 80])?;
+    out.write_all(&(quads.len() as u32 * 2).to_le_bytes())?;
+    for quad in quads {
+        for triangle in quad.triangles() {
+            let points = std::iter::once(quad.normal).chain(triangle.iter().map(|p| p.coords));
+            for p in points {
+                for c in p.iter() {
+                    out.write_all(&(*c as f32).to_le_bytes())?;
+                }
+            }
+            out.write_all(&[0; 2])?;
+        }
+    }
+    out.flush()
 }
 
 struct Grid {
@@ -136,3 +557,165 @@ const ADJACENT_OFFSETS: [Vector3<isize>; 6] = [
     vector![0, 0, -1],
     vector![0, 0, 1],
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_faces() {
+        let drops = vec![Point3::new(1, 1, 1), Point3::new(2, 1, 1)];
+        let mut grid = Grid::new();
+        for drop in drops.iter() {
+            grid.set(drop);
+        }
+
+        let faces = exposed_faces(&grid, &drops);
+        assert_eq!(faces.len(), 10);
+        for quad in faces.iter().map(Face::quad) {
+            let c = quad.corners;
+            assert_eq!((c[1] - c[0]).cross(&(c[2] - c[1])), quad.normal);
+        }
+
+        // The long sides merge into one quad each, the ends stay separate.
+        let merged = merge_faces(&faces);
+        assert_eq!(merged.len(), 6);
+        assert_eq!(merged.iter().map(Quad::area).sum::<isize>(), 10);
+        for quad in merged {
+            let c = quad.corners;
+            let n = (c[1] - c[0]).cross(&(c[2] - c[1]));
+            assert_eq!(n / n.abs().sum(), quad.normal);
+        }
+    }
+
+    #[test]
+    fn mesh_writers() {
+        let drops = vec![Point3::new(1, 1, 1), Point3::new(2, 1, 1)];
+        let mut grid = Grid::new();
+        for drop in drops.iter() {
+            grid.set(drop);
+        }
+        let faces = exposed_faces(&grid, &drops);
+        let count = |out: &str, prefix: &str| out.lines().filter(|l| l.starts_with(prefix)).count();
+
+        // Separate faces share the 12 corners of the 2x1x1 box's unit cells;
+        // merged ones only its 8 outer corners.
+        for (quads, num_vertices) in [
+            (faces.iter().map(Face::quad).collect::<Vec<_>>(), 12),
+            (merge_faces(&faces), 8),
+        ] {
+            let mut obj = Vec::new();
+            write_obj(&quads, &mut obj).unwrap();
+            let obj = String::from_utf8(obj).unwrap();
+            assert_eq!(count(&obj, "f "), quads.len());
+            assert_eq!(count(&obj, "v "), num_vertices);
+            assert_eq!(count(&obj, "vn "), 6);
+
+            let mut stl = Vec::new();
+            write_stl_ascii(&quads, &mut stl).unwrap();
+            let stl = String::from_utf8(stl).unwrap();
+            assert_eq!(count(stl.trim_start(), "solid"), 1);
+            let facets = stl
+                .lines()
+                .filter(|l| l.trim_start().starts_with("facet"))
+                .count();
+            let endfacets = stl.lines().filter(|l| l.trim() == "endfacet").count();
+            assert_eq!(facets, 2 * quads.len());
+            assert_eq!(endfacets, facets);
+
+            let mut stl = Vec::new();
+            write_stl_binary(&quads, &mut stl).unwrap();
+            assert_eq!(stl.len(), 84 + 100 * quads.len());
+            let num_triangles = u32::from_le_bytes(stl[80..84].try_into().unwrap());
+            assert_eq!(num_triangles as usize, 2 * quads.len());
+
+            // Merging must neither lose nor invent any faces.
+            assert_eq!(quads.iter().map(Quad::area).sum::<isize>(), 10);
+        }
+    }
+
+    #[test]
+    fn sample_pockets() {
+        // The puzzle's example has a single pocket of air at 2,2,5.
+        let drops = [
+            (2, 2, 2),
+            (1, 2, 2),
+            (3, 2, 2),
+            (2, 1, 2),
+            (2, 3, 2),
+            (2, 2, 1),
+            (2, 2, 3),
+            (2, 2, 4),
+            (2, 2, 6),
+            (1, 2, 5),
+            (3, 2, 5),
+            (2, 1, 5),
+            (2, 3, 5),
+        ]
+        .map(|(x, y, z)| Point3::new(x, y, z));
+        let mut grid = Grid::new();
+        for drop in drops.iter() {
+            grid.set(drop);
+        }
+
+        let exterior = exterior_air(&grid, &drops);
+        let pockets = air_pockets(&grid, &exterior);
+        assert_eq!(pockets.len(), 1);
+        assert_eq!(pockets[0].cells, vec![Point3::new(2, 2, 5)]);
+        assert_eq!(pockets[0].surface_area, 6);
+        // The cubes around the pocket only touch along edges.
+        assert_eq!(components(|p| grid.get(p)).len(), 6);
+
+        let faces = exposed_faces(&grid, &drops);
+        let outer = faces
+            .iter()
+            .filter(|f| exterior.get(&f.neighbour()))
+            .count();
+        assert_eq!((faces.len(), outer), (64, 58));
+    }
+
+    #[test]
+    fn droplet_at_origin() {
+        // A hollow 3x3x3 shell in the corner of the grid, with a single cube
+        // of air inside.
+        let mut drops = Vec::new();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    if (x, y, z) != (1, 1, 1) {
+                        drops.push(Point3::new(x, y, z));
+                    }
+                }
+            }
+        }
+        let mut grid = Grid::new();
+        for drop in drops.iter() {
+            grid.set(drop);
+        }
+
+        let exterior = exterior_air(&grid, &drops);
+        let faces = exposed_faces(&grid, &drops);
+        let outer = faces
+            .iter()
+            .filter(|f| exterior.get(&f.neighbour()))
+            .count();
+        assert_eq!((faces.len(), outer), (54 + 6, 54));
+        assert!(!exterior.get(&Point3::new(1, 1, 1)));
+        assert!(exterior.get(&Point3::new(-1, 0, 0)));
+        assert!(exterior.get(&Point3::new(3, 3, 3)));
+        assert_eq!(air_pockets(&grid, &exterior).len(), 1);
+    }
+
+    #[test]
+    fn parse_validation() {
+        let drops = parse_drops("1,2,3\n\n3,2,1\n1,2,3\n").unwrap();
+        assert_eq!(drops, vec![Point3::new(1, 2, 3), Point3::new(3, 2, 1)]);
+
+        let err = parse_drops("1,2,3\n1,2,3,4\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{err}");
+        assert!(parse_drops("1,2\n").is_err());
+        assert!(parse_drops("1,x,3\n").is_err());
+        assert!(parse_drops("1,2,-3\n").is_err());
+        assert!(parse_drops("1,2,32\n").is_err());
+    }
+}
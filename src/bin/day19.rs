@@ -12,268 +12,466 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use lazy_static::lazy_static;
+use std::io::Read;
 
 fn main() {
-    let blueprints: Vec<Blueprint> = std::io::stdin()
-        .lines()
-        .map(|l| parse_blueprint(&l.unwrap()))
-        .collect();
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let blueprints = parse_blueprints(&input).unwrap_or_else(|e| panic!("{e}"));
 
-    // for (i, blueprint) in blueprints.iter().enumerate() {
+    // for blueprint in blueprints.iter() {
     //     println!("{blueprint:#?}");
-    //     println!("{i}: {}", blueprint.solve());
+    //     println!("{}: {}", blueprint.id, blueprint.solve_default(24));
     // }
 
-    let p1 = blueprints
-        .iter()
-        .enumerate()
-        .map(|(i, b)| (i + 1) as u32 * b.solve(24))
-        .sum::<u32>();
+    let solve = |b: &Blueprint, num_minutes| {
+        b.solve_default(num_minutes)
+            .unwrap_or_else(|e| panic!("{e}"))
+    };
+    let p1 = blueprints.iter().map(|b| b.id * solve(b, 24)).sum::<u32>();
     let p2 = blueprints
         .iter()
         .take(3)
-        .map(|b| b.solve(32))
+        .map(|b| solve(b, 32))
         .product::<u32>();
     println!("{p1} {p2}");
 }
 
-fn parse_blueprint(desc: &str) -> Blueprint {
-    use regex::Regex;
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Number(u32),
+    Colon,
+    Period,
+}
+
+// Splits the input into words, numbers and punctuation, paired with the line
+// each token came from. Line breaks are otherwise insignificant, so
+// blueprints may be given one per line or wrapped across several.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token<'_>)>, String> {
+    let mut tokens = Vec::new();
+    for (line_idx, line) in input.lines().enumerate() {
+        let line_num = line_idx + 1;
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
 
-    lazy_static! {
-        static ref BLUEPRINT_REGEX: Regex = {
-            Regex::new(r#"Blueprint [0-9]+: Each ore robot costs (?P<ore>[ a-zA-Z0-9]+)\. Each clay robot costs (?P<clay>[ a-zA-Z0-9]+)\. Each obsidian robot costs (?P<obsidian>[ a-zA-Z0-9]+)\. Each geode robot costs (?P<geode>[ a-zA-Z0-9]+)\.$"#).unwrap()
-        };
-        static ref COST_REGEX: Regex = {
-            Regex::new(r#"(?P<ore>[0-9]+) ore(?: and (?P<clay>[0-9]+) clay)?(?: and (?P<obsidian>[0-9]+) obsidian)?$"#).unwrap()
-        };
+            let (token, len) = match c {
+                ':' => (Token::Colon, 1),
+                '.' => (Token::Period, 1),
+                _ if c.is_ascii_digit() => {
+                    let len = rest
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    let number = rest[..len].parse().map_err(|e| {
+                        format!("line {line_num}: bad number {:?}: {e}", &rest[..len])
+                    })?;
+                    (Token::Number(number), len)
+                }
+                _ if c.is_ascii_alphabetic() => {
+                    let len = rest
+                        .find(|c: char| !c.is_ascii_alphabetic())
+                        .unwrap_or(rest.len());
+                    (Token::Word(&rest[..len]), len)
+                }
+                _ => return Err(format!("line {line_num}: unexpected character {c:?}")),
+            };
+            tokens.push((line_num, token));
+            rest = &rest[len..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_blueprints(input: &str) -> Result<Vec<Blueprint>, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+    };
+
+    let mut blueprints = Vec::new();
+    while !parser.at_end() {
+        blueprints.push(parser.blueprint()?);
+    }
+    Ok(blueprints)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(usize, Token<'a>)],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn at_end(&self) -> bool {
+        self.pos == self.tokens.len()
     }
 
-    let cost_matches = BLUEPRINT_REGEX.captures(desc).unwrap();
-    assert_eq!(cost_matches.get(0).unwrap().as_str(), desc);
-
-    fn parse_cost(desc: &str) -> Resources {
-        let fields = COST_REGEX.captures(desc).unwrap();
-        assert_eq!(fields.get(0).unwrap().as_str(), desc);
-        let get_field = |name| (|| fields.name(name)?.as_str().parse::<u32>().ok())().unwrap_or(0);
-        Resources {
-            ore: get_field("ore"),
-            clay: get_field("clay"),
-            obsidian: get_field("obsidian"),
-            geode: 0,
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|&(_, t)| t)
+    }
+
+    // Line of the next token, or of the last one at the end of the input.
+    fn line_num(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |&(l, _)| l)
+    }
+
+    fn error(&self, msg: &str) -> String {
+        let line_num = self.line_num();
+        match self.peek() {
+            Some(token) => format!("line {line_num}: {msg}, found {token:?}"),
+            None => format!("line {line_num}: {msg}, found end of input"),
         }
     }
 
-    Blueprint {
-        ore_cost: parse_cost(cost_matches.name("ore").unwrap().as_str()),
-        clay_cost: parse_cost(cost_matches.name("clay").unwrap().as_str()),
-        obsidian_cost: parse_cost(cost_matches.name("obsidian").unwrap().as_str()),
-        geode_cost: parse_cost(cost_matches.name("geode").unwrap().as_str()),
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected {expected:?}")));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn word(&mut self) -> Result<&'a str, String> {
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => Err(self.error("expected a word")),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        match self.peek() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => Err(self.error("expected a number")),
+        }
+    }
+
+    // Blueprint <id>: Each <resource> robot costs <n> <resource> [and <n> <resource>]*. ...
+    fn blueprint(&mut self) -> Result<Blueprint, String> {
+        self.expect(Token::Word("Blueprint"))?;
+        let id = self.number()?;
+        self.expect(Token::Colon)?;
+
+        // Costs may refer to resources whose robot is only described later,
+        // so names are resolved once the whole blueprint has been read.
+        let mut recipe_descs = Vec::<(&str, Vec<(usize, u32, &str)>)>::new();
+        while self.peek() == Some(Token::Word("Each")) {
+            self.next();
+            let robot = self.word()?;
+            self.expect(Token::Word("robot"))?;
+            self.expect(Token::Word("costs"))?;
+            let mut cost = Vec::new();
+            loop {
+                let line_num = self.line_num();
+                let amount = self.number()?;
+                let resource = self.word()?;
+                cost.push((line_num, amount, resource));
+                if self.peek() != Some(Token::Word("and")) {
+                    break;
+                }
+                self.next();
+            }
+            self.expect(Token::Period)?;
+            recipe_descs.push((robot, cost));
+        }
+        if recipe_descs.is_empty() {
+            return Err(format!(
+                "line {}: blueprint {id} has no robots",
+                self.line_num()
+            ));
+        }
+
+        let mut resources = Vec::<String>::new();
+        for &(robot, _) in &recipe_descs {
+            if !resources.iter().any(|r| r == robot) {
+                resources.push(robot.to_string());
+            }
+        }
+
+        let mut recipes = Vec::new();
+        for (robot, cost) in recipe_descs {
+            let mut amounts = Resources::zero(resources.len());
+            for (line_num, amount, resource) in cost {
+                let idx = resources
+                    .iter()
+                    .position(|r| r == resource)
+                    .ok_or_else(|| {
+                        format!("line {line_num}: blueprint {id}: unknown resource {resource:?}")
+                    })?;
+                amounts.0[idx] += amount;
+            }
+            recipes.push(Recipe {
+                produces: resources.iter().position(|r| r == robot).unwrap(),
+                cost: amounts,
+            });
+        }
+
+        Ok(Blueprint {
+            id,
+            resources,
+            recipes,
+        })
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 struct Blueprint {
-    ore_cost: Resources,
-    clay_cost: Resources,
-    obsidian_cost: Resources,
-    geode_cost: Resources,
+    id: u32,
+    // Resource names, indexed by resource ID.
+    resources: Vec<String>,
+    recipes: Vec<Recipe>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Recipe {
+    // Resource that the robot built by this recipe collects.
+    produces: usize,
+    cost: Resources,
 }
 
 impl Blueprint {
-    fn solve(&self, num_minutes: usize) -> u32 {
+    fn resource(&self, name: &str) -> Option<usize> {
+        self.resources.iter().position(|r| r == name)
+    }
+
+    // Solves the puzzle's scenario: start with a single ore robot and
+    // maximise geodes. Fails if the blueprint doesn't have both.
+    fn solve_default(&self, num_minutes: usize) -> Result<u32, String> {
+        let resource = |name| {
+            self.resource(name)
+                .ok_or_else(|| format!("blueprint {} has no {name} robot", self.id))
+        };
+        let ore = resource("ore")?;
+        let geode = resource("geode")?;
+
+        let mut initial_bots = Resources::zero(self.resources.len());
+        initial_bots.0[ore] = 1;
+        Ok(self.solve(num_minutes, &initial_bots, geode))
+    }
+
+    fn solve(&self, num_minutes: usize, initial_bots: &Resources, goal: usize) -> u32 {
         use good_lp::*;
 
-        #[derive(Clone, Copy, Debug)]
+        let num_resources = self.resources.len();
+
+        #[derive(Clone, Debug)]
         struct RoundVariables {
-            // Action to take this round.
-            // build_nothing: Variable,
-            build_ore: Variable,
-            build_clay: Variable,
-            build_obsidian: Variable,
-            build_geode: Variable,
-
-            // Number of operational bots at beginning of round.
-            numbots_ore: Variable,
-            numbots_clay: Variable,
-            numbots_obsidian: Variable,
-            numbots_geode: Variable,
-
-            // Amount of resources available at end of round.
-            total_ore: Variable,
-            total_clay: Variable,
-            total_obsidian: Variable,
-            total_geode: Variable,
+            // Action to take this round, indexed by recipe.
+            build: Vec<Variable>,
+
+            // Number of operational bots at beginning of round, indexed by
+            // resource.
+            numbots: Vec<Variable>,
+
+            // Amount of resources available at end of round, indexed by
+            // resource.
+            total: Vec<Variable>,
         }
 
         let mut round_variables = Vec::<RoundVariables>::new();
 
-        let vars = std::cell::RefCell::new(ProblemVariables::new());
+        let mut vars = ProblemVariables::new();
         for minute in 0..num_minutes {
-            let make_choice = |choice| {
-                vars.borrow_mut()
-                    .add(variable().binary().name(format!("{minute}_build_{choice}")))
-            };
-            let make_bots = |resource| {
-                vars.borrow_mut().add(
-                    variable()
-                        .integer()
-                        .name(format!("{minute}_numbots_{resource}")),
-                )
-            };
-            let make_total = |resource| {
-                vars.borrow_mut().add(
-                    variable()
-                        .integer()
-                        .name(format!("{minute}_total_{resource}")),
-                )
-            };
+            let build = (0..self.recipes.len())
+                .map(|i| {
+                    let robot = &self.resources[self.recipes[i].produces];
+                    vars.add(
+                        variable()
+                            .binary()
+                            .name(format!("{minute}_build_{i}_{robot}")),
+                    )
+                })
+                .collect();
+            let numbots = self
+                .resources
+                .iter()
+                .map(|resource| {
+                    vars.add(
+                        variable()
+                            .integer()
+                            .name(format!("{minute}_numbots_{resource}")),
+                    )
+                })
+                .collect();
+            let total = self
+                .resources
+                .iter()
+                .map(|resource| {
+                    vars.add(
+                        variable()
+                            .integer()
+                            .name(format!("{minute}_total_{resource}")),
+                    )
+                })
+                .collect();
             round_variables.push(RoundVariables {
-                // build_nothing: make_choice("nothing"),
-                build_ore: make_choice("ore"),
-                build_clay: make_choice("clay"),
-                build_obsidian: make_choice("obsidian"),
-                build_geode: make_choice("geode"),
-                numbots_ore: make_bots("ore"),
-                numbots_clay: make_bots("clay"),
-                numbots_obsidian: make_bots("obsidian"),
-                numbots_geode: make_bots("geode"),
-                total_ore: make_total("ore"),
-                total_clay: make_total("clay"),
-                total_obsidian: make_total("obsidian"),
-                total_geode: make_total("geode"),
+                build,
+                numbots,
+                total,
             });
         }
-        let vars = vars.into_inner();
 
         let rfinal = round_variables.last().unwrap();
-        let max_geodes = rfinal.total_geode;
-        let mut prob = vars.maximise(max_geodes.clone()).using(default_solver);
-
-        let r1 = round_variables[0];
-        prob.add_constraint(constraint::eq(r1.build_ore, 0));
-        prob.add_constraint(constraint::eq(r1.build_clay, 0));
-        prob.add_constraint(constraint::eq(r1.build_obsidian, 0));
-        prob.add_constraint(constraint::eq(r1.build_geode, 0));
-        prob.add_constraint(constraint::eq(r1.numbots_ore, 1));
-        prob.add_constraint(constraint::eq(r1.numbots_clay, 0));
-        prob.add_constraint(constraint::eq(r1.numbots_obsidian, 0));
-        prob.add_constraint(constraint::eq(r1.numbots_geode, 0));
-        prob.add_constraint(constraint::eq(r1.total_ore, 1));
-        prob.add_constraint(constraint::eq(r1.total_clay, 0));
-        prob.add_constraint(constraint::eq(r1.total_obsidian, 0));
-        prob.add_constraint(constraint::eq(r1.total_geode, 0));
+        let max_goal = rfinal.total[goal];
+        let mut prob = vars.maximise(max_goal).using(default_solver);
+
+        let r1 = &round_variables[0];
+        for &build in &r1.build {
+            prob.add_constraint(constraint::eq(build, 0));
+        }
+        for r in 0..num_resources {
+            prob.add_constraint(constraint::eq(r1.numbots[r], initial_bots.0[r]));
+            prob.add_constraint(constraint::eq(r1.total[r], initial_bots.0[r]));
+        }
 
         for minute in 1..num_minutes {
-            let rv_prev = round_variables[minute - 1];
-            let rv = round_variables[minute];
+            let rv_prev = &round_variables[minute - 1];
+            let rv = &round_variables[minute];
 
             // Can only do one action per minute.
             prob.add_constraint(constraint::leq(
-                /* rv.build_nothing
-                + */
-                rv.build_ore + rv.build_clay + rv.build_obsidian + rv.build_geode,
+                rv.build.iter().copied().sum::<Expression>(),
                 1,
             ));
 
-            // Number of operational bots this minute is number from last round plus the bot that was constructed.
-            prob.add_constraint(constraint::eq(
-                rv.numbots_ore,
-                rv_prev.numbots_ore + rv_prev.build_ore,
-            ));
-            prob.add_constraint(constraint::eq(
-                rv.numbots_clay,
-                rv_prev.numbots_clay + rv_prev.build_clay,
-            ));
-            prob.add_constraint(constraint::eq(
-                rv.numbots_obsidian,
-                rv_prev.numbots_obsidian + rv_prev.build_obsidian,
-            ));
-            prob.add_constraint(constraint::eq(
-                rv.numbots_geode,
-                rv_prev.numbots_geode + rv_prev.build_geode,
-            ));
+            for r in 0..num_resources {
+                // Number of operational bots this minute is number from last round plus the bot that was constructed.
+                let built = self
+                    .recipes
+                    .iter()
+                    .zip(&rv_prev.build)
+                    .filter(|(recipe, _)| recipe.produces == r)
+                    .map(|(_, &build)| build)
+                    .sum::<Expression>();
+                prob.add_constraint(constraint::eq(rv.numbots[r], rv_prev.numbots[r] + built));
 
-            let ore_cost = rv.build_ore * self.ore_cost.ore
-                + rv.build_clay * self.clay_cost.ore
-                + rv.build_obsidian * self.obsidian_cost.ore
-                + rv.build_geode * self.geode_cost.ore;
-            let clay_cost = rv.build_obsidian * self.obsidian_cost.clay;
-            let obsidian_cost = rv.build_geode * self.geode_cost.obsidian;
-
-            // Cannot dip below 0 for any resource when constructing a bot.
-            prob.add_constraint(constraint::geq(rv_prev.total_ore, ore_cost.clone()));
-            prob.add_constraint(constraint::geq(rv_prev.total_clay, clay_cost.clone()));
-            prob.add_constraint(constraint::geq(
-                rv_prev.total_obsidian,
-                obsidian_cost.clone(),
-            ));
+                let cost = self
+                    .recipes
+                    .iter()
+                    .zip(&rv.build)
+                    .map(|(recipe, &build)| build * recipe.cost.0[r])
+                    .sum::<Expression>();
 
-            // Total resources at end of this round is total from end of prev
-            // round, plus number of bots operational this round, minus cost of
-            // building bot this round.
-            prob.add_constraint(constraint::eq(
-                rv.total_ore,
-                rv_prev.total_ore + rv.numbots_ore - ore_cost,
-            ));
-            prob.add_constraint(constraint::eq(
-                rv.total_clay,
-                rv_prev.total_clay + rv.numbots_clay - clay_cost,
-            ));
-            prob.add_constraint(constraint::eq(
-                rv.total_obsidian,
-                rv_prev.total_obsidian + rv.numbots_obsidian - obsidian_cost,
-            ));
-            prob.add_constraint(constraint::eq(
-                rv.total_geode,
-                rv_prev.total_geode + rv.numbots_geode,
-            ));
+                // Cannot dip below 0 for any resource when constructing a bot.
+                prob.add_constraint(constraint::geq(rv_prev.total[r], cost.clone()));
+
+                // Total resources at end of this round is total from end of prev
+                // round, plus number of bots operational this round, minus cost of
+                // building bot this round.
+                prob.add_constraint(constraint::eq(
+                    rv.total[r],
+                    rv_prev.total[r] + rv.numbots[r] - cost,
+                ));
+            }
         }
 
         let sol = prob.solve().unwrap();
 
         // for minute in 0..24 {
         //     println!("== Minute {minute} ==");
-        //     let rv = round_variables[minute].as_ref().unwrap();
-        //     for v in [
-        //         // rv.build_nothing,
-        //         rv.build_ore,
-        //         rv.build_clay,
-        //         rv.build_obsidian,
-        //         rv.build_geode,
-        //     ] {
+        //     let rv = &round_variables[minute];
+        //     for &v in &rv.build {
         //         print!("{} ", sol.eval(v));
         //     }
         //     println!();
-        //     for v in [
-        //         rv.numbots_ore,
-        //         rv.numbots_clay,
-        //         rv.numbots_obsidian,
-        //         rv.numbots_geode,
-        //     ] {
+        //     for &v in &rv.numbots {
         //         print!("{} ", sol.eval(v));
         //     }
         //     println!();
-        //     for v in [
-        //         rv.total_ore,
-        //         rv.total_clay,
-        //         rv.total_obsidian,
-        //         rv.total_geode,
-        //     ] {
+        //     for &v in &rv.total {
         //         print!("{} ", sol.eval(v));
         //     }
         //     println!();
         // }
 
-        sol.eval(max_geodes) as u32
+        sol.eval(max_goal) as u32
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-struct Resources {
-    ore: u32,
-    clay: u32,
-    obsidian: u32,
-    geode: u32,
+// Amount of each resource, indexed by resource ID.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Resources(Vec<u32>);
+
+impl Resources {
+    fn zero(num_resources: usize) -> Resources {
+        Resources(vec![0; num_resources])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_wrapped_blueprint() {
+        let single = "Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian.\n";
+        let wrapped = "Blueprint 2:
+  Each ore robot costs 2 ore.
+  Each clay robot costs 3 ore.
+  Each obsidian robot costs 3 ore and 8 clay.
+  Each geode robot costs 3 ore and 12 obsidian.
+";
+        let single = parse_blueprints(single).unwrap();
+        let wrapped = parse_blueprints(wrapped).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].id, 2);
+        assert_eq!(single[0].resources, wrapped[0].resources);
+        assert_eq!(single[0].recipes, wrapped[0].recipes);
+        assert_eq!(single[0].recipes[3].cost, Resources(vec![3, 0, 12, 0]));
+    }
+
+    #[test]
+    fn parse_unknown_resource() {
+        let err =
+            parse_blueprints("Blueprint 1: Each ore robot costs 4 ore and 2 diamond.").unwrap_err();
+        assert!(err.contains("unknown resource \"diamond\""), "{err}");
+    }
+
+    #[test]
+    fn parse_other_resources() {
+        let blueprints = parse_blueprints(
+            "Blueprint 7: Each wood robot costs 1 wood. \
+             Each plank robot costs 2 wood. \
+             Each chair robot costs 3 plank and 1 wood.",
+        )
+        .unwrap();
+        let b = &blueprints[0];
+        assert_eq!(b.id, 7);
+        assert_eq!(b.resources, ["wood", "plank", "chair"]);
+        assert_eq!(b.resource("chair"), Some(2));
+        assert_eq!(b.recipes[2].produces, 2);
+        assert_eq!(b.recipes[2].cost, Resources(vec![1, 3, 0]));
+
+        let err = b.solve_default(24).unwrap_err();
+        assert_eq!(err, "blueprint 7 has no ore robot");
+    }
+
+    #[test]
+    fn parse_errors_at_end() {
+        let err = parse_blueprints("Blueprint 1:\n\n").unwrap_err();
+        assert_eq!(err, "line 1: blueprint 1 has no robots");
+
+        let err = parse_blueprints("Blueprint 1:\n  Each ore robot costs 4 ore\n").unwrap_err();
+        assert_eq!(err, "line 2: expected Period, found end of input");
+    }
 }
@@ -15,37 +15,30 @@
 fn main() {
     let lines: Vec<String> = std::io::stdin().lines().map(|l| l.unwrap()).collect();
 
-    let mut first_command = 0;
-    let mut num_stacks = 0;
-    let mut crates = Vec::<char>::new();
-    for (r, l) in lines.iter().enumerate() {
-        let mut iter = l.chars().skip(1).step_by(4).peekable();
-        if iter.peek().unwrap().is_digit(10) {
-            first_command = r + 2;
-            break;
-        }
-        crates.extend(iter);
-        if r == 0 {
-            num_stacks = crates.len();
-        } else {
-            assert_eq!(crates.len() % num_stacks, 0);
-        }
-    }
-
-    let mut stacks: Vec<Vec<char>> = vec![Vec::new(); num_stacks];
-    for s in 0..num_stacks {
-        for r in (0..crates.len() / num_stacks).rev() {
-            let c = crates[r * num_stacks + s];
-            if c == ' ' {
-                break;
+    let mut steps = false;
+    let mut capacity = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => steps = true,
+            "--capacity" => {
+                capacity = args.next().and_then(|v| v.parse().ok()).filter(|&n| n > 0);
+                assert!(capacity.is_some(), "--capacity needs a positive number");
             }
-            stacks[s].push(c);
+            _ => panic!("unknown argument {arg:?}"),
         }
     }
 
+    // The drawing ends with the line numbering the stacks.
+    let num_line = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap();
+    let stacks = parse_drawing(&lines[..num_line]);
+
     let cmds: Vec<Command> = lines
         .iter()
-        .skip(first_command)
+        .skip(num_line + 2)
         .map(|l| {
             let mut iter = l.split(' ');
             assert_eq!(iter.next().unwrap(), "move");
@@ -58,56 +51,139 @@ fn main() {
         })
         .collect();
 
-    for r in 0..crates.len() / num_stacks {
-        for s in 0..num_stacks {
-            print!("{} ", crates[r * num_stacks + s]);
+    let solve = |crane: &dyn Crane| {
+        if steps {
+            print!("{}", render(&stacks));
         }
-        println!();
+        let end = run(crane, stacks.clone(), &cmds, |cmd, stacks| {
+            if steps {
+                print!("\n{cmd}\n\n{}", render(stacks));
+            }
+        });
+        if steps {
+            println!();
+        }
+        println!("{}", tops(&end));
+    };
+
+    // Part 1
+    solve(&CrateMover9000);
+
+    // Part 2
+    solve(&CrateMover9001);
+
+    // `--capacity N` adds a crane that lifts at most N crates at a time.
+    if let Some(capacity) = capacity {
+        solve(&LimitedCrane { capacity });
     }
-    println!();
+}
+
+type Stacks = Vec<Vec<char>>;
+
+// Stacks are numbered from 1 in commands, and the top crate is the last in
+// each `Vec`.
+trait Crane {
+    fn apply(&self, stacks: &mut Stacks, cmd: &Command);
+}
+
+struct CrateMover9000;
 
-    for stack in stacks.iter() {
-        for c in stack.iter() {
-            print!("{c} ");
+impl Crane for CrateMover9000 {
+    fn apply(&self, stacks: &mut Stacks, cmd: &Command) {
+        for _i in 0..cmd.cnt {
+            let c = stacks[cmd.src - 1].pop().unwrap();
+            stacks[cmd.dst - 1].push(c);
         }
-        println!();
     }
-    println!();
+}
 
-    for cmd in cmds.iter() {
-        println!("# {} {} -> {}", cmd.cnt, cmd.src, cmd.dst);
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn apply(&self, stacks: &mut Stacks, cmd: &Command) {
+        let split_point = stacks[cmd.src - 1].len() - cmd.cnt;
+        let load = stacks[cmd.src - 1].split_off(split_point);
+        stacks[cmd.dst - 1].extend(load);
     }
+}
 
-    println!();
+// Moves `capacity` crates at a time like a 9001.
+struct LimitedCrane {
+    capacity: usize,
+}
 
-    // Part 1
-    let mut p1_stacks = stacks.clone();
-    for cmd in cmds.iter() {
-        let Command { cnt, src, dst } = *cmd;
-        for _i in 0..cnt {
-            let c = p1_stacks[src - 1].pop().unwrap();
-            p1_stacks[dst - 1].push(c);
+impl Crane for LimitedCrane {
+    fn apply(&self, stacks: &mut Stacks, cmd: &Command) {
+        let mut left = cmd.cnt;
+        while left > 0 {
+            let cnt = left.min(self.capacity);
+            CrateMover9001.apply(stacks, &Command { cnt, ..*cmd });
+            left -= cnt;
         }
     }
+}
 
-    for s in p1_stacks {
-        print!("{}", s.last().unwrap());
+fn run(
+    crane: &dyn Crane,
+    mut stacks: Stacks,
+    cmds: &[Command],
+    mut after_move: impl FnMut(&Command, &Stacks),
+) -> Stacks {
+    for cmd in cmds.iter() {
+        crane.apply(&mut stacks, cmd);
+        after_move(cmd, &stacks);
     }
-    println!();
+    stacks
+}
 
-    // Part 2
-    let mut p2_stacks = stacks.clone();
-    for cmd in cmds.iter() {
-        let Command { cnt, src, dst } = *cmd;
-        let split_point = p2_stacks[src - 1].len() - cnt;
-        let load = p2_stacks[src - 1].split_off(split_point);
-        p2_stacks[dst - 1].extend(load);
+// Rows must be padded out to the full width.
+fn parse_drawing(rows: &[impl AsRef<str>]) -> Stacks {
+    let mut num_stacks = 0;
+    let mut crates = Vec::<char>::new();
+    for (r, l) in rows.iter().enumerate() {
+        crates.extend(l.as_ref().chars().skip(1).step_by(4));
+        if r == 0 {
+            num_stacks = crates.len();
+        } else {
+            assert_eq!(crates.len() % num_stacks, 0);
+        }
     }
 
-    for s in p2_stacks {
-        print!("{}", s.last().unwrap());
+    let mut stacks: Stacks = vec![Vec::new(); num_stacks];
+    for s in 0..num_stacks {
+        for r in (0..crates.len() / num_stacks).rev() {
+            let c = crates[r * num_stacks + s];
+            if c == ' ' {
+                break;
+            }
+            stacks[s].push(c);
+        }
     }
-    println!();
+    stacks
+}
+
+fn render(stacks: &Stacks) -> String {
+    let height = stacks.iter().map(Vec::len).max().unwrap_or(0);
+    let mut out = String::new();
+    for level in (0..height).rev() {
+        let row: Vec<String> = stacks
+            .iter()
+            .map(|s| match s.get(level) {
+                Some(c) => format!("[{c}]"),
+                None => "   ".to_string(),
+            })
+            .collect();
+        out += &row.join(" ");
+        out.push('\n');
+    }
+    let numbers: Vec<String> = (1..=stacks.len()).map(|i| format!("{i:^3}")).collect();
+    out += &numbers.join(" ");
+    out.push('\n');
+    out
+}
+
+fn tops(stacks: &Stacks) -> String {
+    stacks.iter().filter_map(|s| s.last()).collect()
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -116,3 +192,63 @@ struct Command {
     src: usize,
     dst: usize,
 }
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "move {} from {} to {}", self.cnt, self.src, self.dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cranes() {
+        let stacks = vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']];
+        let cmds = [(1, 2, 1), (3, 1, 3), (2, 2, 1), (1, 1, 2)].map(|(cnt, src, dst)| Command {
+            cnt,
+            src,
+            dst,
+        });
+
+        assert_eq!(
+            tops(&run(&CrateMover9000, stacks.clone(), &cmds, |_, _| ())),
+            "CMZ"
+        );
+        assert_eq!(
+            tops(&run(&CrateMover9001, stacks.clone(), &cmds, |_, _| ())),
+            "MCD"
+        );
+        for (capacity, expected) in [(1, "CMZ"), (2, "MCZ"), (3, "MCD")] {
+            let crane = LimitedCrane { capacity };
+            assert_eq!(
+                tops(&run(&crane, stacks.clone(), &cmds, |_, _| ())),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn drawing() {
+        let drawing = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n";
+        let rows: Vec<&str> = drawing.lines().collect();
+        let stacks = parse_drawing(&rows[..3]);
+        assert_eq!(stacks, [vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']]);
+        assert_eq!(render(&stacks), drawing);
+
+        let cmds = [Command {
+            cnt: 3,
+            src: 2,
+            dst: 1,
+        }];
+        let mut shown = Vec::new();
+        run(&CrateMover9000, stacks, &cmds, |cmd, stacks| {
+            shown.push(format!("{cmd}\n{}", render(stacks)))
+        });
+        assert_eq!(
+            shown,
+            ["move 3 from 2 to 1\n[M]        \n[C]        \n[D]        \n[N]        \n[Z]     [P]\n 1   2   3 \n"]
+        );
+    }
+}
@@ -11,3 +11,109 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+// Walks the command-line flags a day's binary takes on top of its input, so
+// each `Config::from_args` only has to match on the flag names.
+pub struct Args<I> {
+    args: I,
+    flag: String,
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    pub fn new(args: I) -> Args<I> {
+        Args {
+            args,
+            flag: String::new(),
+        }
+    }
+
+    pub fn next_flag(&mut self) -> Option<&str> {
+        self.flag = self.args.next()?;
+        Some(&self.flag)
+    }
+
+    // The flag last returned by `next_flag`.
+    pub fn flag(&self) -> &str {
+        &self.flag
+    }
+
+    // The argument following the current flag.
+    pub fn string(&mut self) -> String {
+        self.args
+            .next()
+            .unwrap_or_else(|| panic!("{} needs a value", self.flag))
+    }
+
+    pub fn value<T: FromStr>(&mut self) -> T
+    where
+        T::Err: Display,
+    {
+        let v = self.string();
+        self.parse(&v)
+    }
+
+    // Parses `v`, which is all or part of the current flag's value.
+    pub fn parse<T: FromStr>(&self, v: &str) -> T
+    where
+        T::Err: Display,
+    {
+        v.parse()
+            .unwrap_or_else(|e| panic!("bad value for {}: {v:?}: {e}", self.flag))
+    }
+
+    pub fn unknown(&self) -> ! {
+        panic!("unknown argument {:?}", self.flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Args<impl Iterator<Item = String>> {
+        Args::new(
+            args.iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    }
+
+    #[test]
+    fn flags_and_values() {
+        let mut args = args(&["--a", "12", "--b", "x,y"]);
+        assert_eq!(args.next_flag(), Some("--a"));
+        assert_eq!(args.value::<u32>(), 12);
+        assert_eq!(args.next_flag(), Some("--b"));
+        assert_eq!(args.flag(), "--b");
+        assert_eq!(args.string(), "x,y");
+        assert_eq!(args.next_flag(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "--a needs a value")]
+    fn missing_value() {
+        let mut args = args(&["--a"]);
+        args.next_flag();
+        args.string();
+    }
+
+    #[test]
+    #[should_panic(expected = "bad value for --a: \"-1\": invalid digit found in string")]
+    fn bad_value() {
+        let mut args = args(&["--a", "-1"]);
+        args.next_flag();
+        args.value::<u32>();
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown argument \"--c\"")]
+    fn unknown_flag() {
+        let mut args = args(&["--c"]);
+        args.next_flag();
+        args.unknown();
+    }
+}